@@ -3,13 +3,42 @@ use anyhow::Error;
 pub use command::FuzzCommand;
 use fehler::throws;
 
-use trdelnik_fuzz::commander::Commander;
+use trdelnik_fuzz::commander::{Commander, FuzzRunOptions};
 
 #[throws]
 pub async fn fuzz_test(fuzz_test_command: FuzzCommand) {
     let commander = Commander::default();
     match fuzz_test_command {
-        FuzzCommand::Run { test_name } => commander.run_fuzz_test(test_name).await?,
-        FuzzCommand::New { test_name } => commander.new_fuzz_test(test_name).await?,
+        FuzzCommand::Run {
+            test_name,
+            seed,
+            sequences,
+            duration,
+            iterations,
+            report,
+        } => {
+            let options = FuzzRunOptions {
+                seed,
+                sequences,
+                duration,
+                iterations,
+                report,
+            };
+            commander.run_fuzz_test(test_name, options).await?
+        }
+        FuzzCommand::Replay { test_name, case } => {
+            commander.replay_fuzz_test(test_name, case).await?
+        }
+        FuzzCommand::Honggfuzz { test_name } => commander.run_honggfuzz(test_name).await?,
+        FuzzCommand::New {
+            test_name,
+            honggfuzz,
+        } => {
+            if honggfuzz {
+                commander.new_honggfuzz_test(test_name).await?
+            } else {
+                commander.new_fuzz_test(test_name).await?
+            }
+        }
     }
 }
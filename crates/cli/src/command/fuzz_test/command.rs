@@ -1,3 +1,5 @@
+use std::{path::PathBuf, time::Duration};
+
 use clap::Subcommand;
 
 #[derive(Subcommand)]
@@ -7,11 +9,42 @@ pub enum FuzzCommand {
         /// Anchor project root
         #[clap()]
         test_name: String,
+        /// Master seed of the fuzz run, use it to replay a failed run
+        #[clap(long)]
+        seed: Option<u64>,
+        /// Number of sequences, use `--sequences 1` with the seed of a failed sequence to replay it alone
+        #[clap(long)]
+        sequences: Option<usize>,
+        /// Keep fuzzing until the time budget is exhausted, e.g. `30s`, `10m` or `2h`
+        #[clap(long, parse(try_from_str = humantime::parse_duration))]
+        duration: Option<Duration>,
+        /// Keep fuzzing until the given number of flows is executed
+        #[clap(long)]
+        iterations: Option<u64>,
+        /// Write the end-of-run report as JSON to the file
+        #[clap(long)]
+        report: Option<PathBuf>,
+    },
+    /// Replay the stored cases from the corpus of the fuzz test
+    Replay {
+        #[clap()]
+        test_name: String,
+        /// Name of the case to replay, all cases are replayed if not set
+        #[clap()]
+        case: Option<String>,
+    },
+    /// Run fuzz tests with the coverage-guided honggfuzz engine
+    Honggfuzz {
+        #[clap()]
+        test_name: String,
     },
     /// Generate fuzz tests
     New {
         /// Anchor project root
         #[clap()]
         test_name: String,
+        /// Generate a honggfuzz target for the in-process backend instead of a fuzz test
+        #[clap(long)]
+        honggfuzz: bool,
     },
 }
@@ -0,0 +1,191 @@
+use anchor_client::{
+    anchor_lang,
+    solana_client::{
+        client_error::ClientErrorKind,
+        rpc_request::{RpcError, RpcResponseErrorData},
+        rpc_response::RpcSimulateTransactionResult,
+    },
+    solana_sdk::{instruction::InstructionError, transaction::TransactionError},
+    ClientError,
+};
+use std::fmt::{self, Debug};
+
+/// Where the Anchor error was raised.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AnchorErrorOrigin {
+    /// A constraint of the account with the given name was violated.
+    Account(String),
+    /// The error was thrown at the given line of the source file, e.g. by `require!`.
+    Source { file: String, line: u32 },
+}
+
+/// A program error of a failed instruction, decoded from the transaction error
+/// and the `AnchorError` log line of the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnchorErrorInfo {
+    /// Index of the failed instruction in the transaction, if known.
+    pub instruction_index: Option<u8>,
+    /// The error code, e.g. `6000` for the first variant of an `#[error_code]` enum.
+    pub code: u32,
+    /// Name of the error, e.g. `Unauthorized`, `None` when the program did not log it.
+    pub name: Option<String>,
+    pub message: Option<String>,
+    pub origin: Option<AnchorErrorOrigin>,
+    /// Logs of the failed transaction, empty when they are not available.
+    pub logs: Vec<String>,
+}
+
+impl AnchorErrorInfo {
+    /// Decodes the error of an instruction failed with `TransactionError::InstructionError(_, Custom(code))`.
+    ///
+    /// Returns `None` for all the other errors, e.g. failed RPC requests or runtime errors.
+    pub fn from_client_error(error: &ClientError) -> Option<Self> {
+        match error {
+            ClientError::SolanaClientError(e) => match e.get_transaction_error()? {
+                TransactionError::InstructionError(index, InstructionError::Custom(code)) => {
+                    let logs = preflight_logs(e.kind()).unwrap_or_default();
+                    Some(Self::from_logs(Some(index), code, logs))
+                }
+                _ => None,
+            },
+            ClientError::AnchorError(anchor_lang::error::Error::AnchorError(e)) => Some(Self {
+                instruction_index: None,
+                code: e.error_code_number,
+                name: Some(e.error_name.clone()),
+                message: Some(e.error_msg.clone()),
+                origin: e.error_origin.as_ref().map(|origin| match origin {
+                    anchor_lang::error::ErrorOrigin::AccountName(account) => {
+                        AnchorErrorOrigin::Account(account.clone())
+                    }
+                    anchor_lang::error::ErrorOrigin::Source(source) => AnchorErrorOrigin::Source {
+                        file: source.filename.to_owned(),
+                        line: source.line,
+                    },
+                }),
+                logs: vec![],
+            }),
+            _ => None,
+        }
+    }
+
+    /// Creates the error from the error code and the transaction logs, the name, the message
+    /// and the origin are parsed from the `AnchorError` log line with the same error code.
+    pub fn from_logs(instruction_index: Option<u8>, code: u32, logs: Vec<String>) -> Self {
+        let parsed = logs
+            .iter()
+            .rev()
+            .filter_map(|log| parse_anchor_error_log(log))
+            .find(|parsed| parsed.code == code);
+        Self {
+            instruction_index,
+            code,
+            name: parsed.as_ref().map(|parsed| parsed.name.clone()),
+            message: parsed.as_ref().map(|parsed| parsed.message.clone()),
+            origin: parsed.and_then(|parsed| parsed.origin),
+            logs,
+        }
+    }
+
+    /// Returns `true` when the error has the code of `error`, e.g. `MyError::Unauthorized`.
+    pub fn is(&self, error: impl Into<u32>) -> bool {
+        self.code == error.into()
+    }
+}
+
+impl fmt::Display for AnchorErrorInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "{name} ({})", self.code)?,
+            None => write!(f, "Custom({})", self.code)?,
+        }
+        if let Some(message) = &self.message {
+            write!(f, ": {message}")?;
+        }
+        match &self.origin {
+            Some(AnchorErrorOrigin::Account(account)) => {
+                write!(f, " (caused by account {account})")
+            }
+            Some(AnchorErrorOrigin::Source { file, line }) => {
+                write!(f, " (thrown in {file}:{line})")
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+/// Asserts that the instruction failed with the given Anchor error and returns the decoded error.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let result = turnstile_instruction::coin(&client, /* ... */).await;
+/// assert_anchor_error(result, TurnstileError::Unauthorized);
+/// ```
+#[track_caller]
+pub fn assert_anchor_error<T>(
+    result: Result<T, ClientError>,
+    expected: impl Into<u32> + Debug,
+) -> AnchorErrorInfo {
+    let error = match result {
+        Ok(_) => panic!("Expected the error {expected:?}, but the transaction succeeded"),
+        Err(error) => error,
+    };
+    let actual = AnchorErrorInfo::from_client_error(&error).unwrap_or_else(|| {
+        panic!("Expected the error {expected:?}, but the transaction failed with: {error}")
+    });
+    let expected_repr = format!("{expected:?}");
+    let expected_code = expected.into();
+    if actual.code != expected_code {
+        panic!("Expected the error {expected_repr} ({expected_code}), but the instruction failed with {actual}");
+    }
+    actual
+}
+
+struct ParsedAnchorError {
+    code: u32,
+    name: String,
+    message: String,
+    origin: Option<AnchorErrorOrigin>,
+}
+
+/// Parses the log line, e.g.
+/// `Program log: AnchorError thrown in programs/turnstile/src/lib.rs:27. Error Code: Unauthorized. Error Number: 6000. Error Message: Not allowed.`.
+fn parse_anchor_error_log(log: &str) -> Option<ParsedAnchorError> {
+    let (_, error) = log.split_once("AnchorError ")?;
+    let (origin, error) = error.split_once(". Error Code: ")?;
+    let (name, error) = error.split_once(". Error Number: ")?;
+    let (code, message) = error.split_once(". Error Message: ")?;
+
+    let origin = if let Some(source) = origin.strip_prefix("thrown in ") {
+        let (file, line) = source.rsplit_once(':')?;
+        Some(AnchorErrorOrigin::Source {
+            file: file.to_owned(),
+            line: line.parse().ok()?,
+        })
+    } else {
+        origin
+            .strip_prefix("caused by account: ")
+            .map(|account| AnchorErrorOrigin::Account(account.to_owned()))
+    };
+
+    Some(ParsedAnchorError {
+        code: code.parse().ok()?,
+        name: name.to_owned(),
+        message: message.strip_suffix('.').unwrap_or(message).to_owned(),
+        origin,
+    })
+}
+
+fn preflight_logs(kind: &ClientErrorKind) -> Option<Vec<String>> {
+    match kind {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError {
+            data:
+                RpcResponseErrorData::SendTransactionPreflightFailure(RpcSimulateTransactionResult {
+                    logs: Some(logs),
+                    ..
+                }),
+            ..
+        }) => Some(logs.clone()),
+        _ => None,
+    }
+}
@@ -1,32 +1,38 @@
-use crate::{config::CONFIG, Reader, TempClone};
+use crate::{config::CONFIG, AnchorErrorInfo, EventCollector, Reader, TempClone};
 use anchor_client::{
     anchor_lang::{
         prelude::System, solana_program::program_pack::Pack, AccountDeserialize, Id,
         InstructionData, ToAccountMetas,
     },
-    solana_client::rpc_config::RpcTransactionConfig,
+    solana_client::rpc_config::{
+        RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig, RpcTransactionConfig,
+    },
     solana_sdk::{
-        account::Account,
+        account::{accounts_equal, Account, AccountSharedData, ReadableAccount},
         bpf_loader,
+        bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+        clock::{Clock, Slot, UnixTimestamp},
         commitment_config::CommitmentConfig,
         instruction::Instruction,
         loader_instruction,
         pubkey::Pubkey,
         signer::{keypair::Keypair, Signer},
-        system_instruction,
-        transaction::Transaction,
+        stake::{self, state::StakeState},
+        system_instruction, sysvar,
+        transaction::{Transaction, TransactionError},
     },
     Client as AnchorClient, ClientError as Error, Program,
 };
 
 use borsh::BorshDeserialize;
-use fehler::throws;
+use fehler::{throw, throws};
 use futures::stream::{self, StreamExt};
 use log::{debug, error};
 use serde::de::DeserializeOwned;
-use solana_account_decoder::parse_token::UiTokenAmount;
+use solana_account_decoder::{parse_token::UiTokenAmount, UiAccountEncoding};
 use solana_cli_output::display::println_transaction;
 use solana_client::nonblocking;
+use solana_runtime::bank::Bank;
 use solana_transaction_status::{EncodedConfirmedTransactionWithStatusMeta, UiTransactionEncoding};
 use solana_validator::test_validator::TestValidator;
 use std::fmt::Debug;
@@ -34,7 +40,13 @@ use std::fmt::Debug;
 // of some crates are required in this `client` crate and `anchor-spl` crate
 #[allow(deprecated)]
 use spl_associated_token_account::{create_associated_token_account, get_associated_token_address};
-use std::{fmt::Formatter, mem, path::PathBuf, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Formatter,
+    mem,
+    path::PathBuf,
+    sync::Arc,
+};
 use std::{thread::sleep, time::Duration};
 use tokio::task;
 use tokio::time;
@@ -53,7 +65,104 @@ pub struct Client {
     rpc_client: nonblocking::rpc_client::RpcClient,
 
     test_validator: Arc<TestValidator>,
-    ledger_path: PathBuf,
+    ledger: Arc<Ledger>,
+}
+
+/// The ledger directory of a validator, it is removed when the last client of the validator
+/// is dropped, i.e. after the validator is stopped.
+struct Ledger(PathBuf);
+
+impl Drop for Ledger {
+    fn drop(&mut self) {
+        self.0.exists().then(|| {
+            std::fs::remove_dir_all(&self.0).unwrap_or_else(|err| {
+                error!(
+                    "Error removing validator ledger {}: {}",
+                    self.0.display(),
+                    err
+                )
+            });
+        });
+    }
+}
+
+/// The accounts and the timestamp of the validator captured by [Client::snapshot].
+#[derive(Clone, Default)]
+pub struct ValidatorSnapshot {
+    accounts: HashMap<Pubkey, AccountSharedData>,
+    unix_timestamp: UnixTimestamp,
+}
+
+impl Debug for ValidatorSnapshot {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ValidatorSnapshot")
+            .field("accounts", &self.accounts.len())
+            .field("unix_timestamp", &self.unix_timestamp)
+            .finish()
+    }
+}
+
+fn store_accounts(bank: &Bank, accounts: &[(Pubkey, AccountSharedData)]) {
+    for (pubkey, account) in accounts {
+        bank.store_account(pubkey, account);
+    }
+    bank.set_capitalization();
+    // Changed programs have to be loaded again
+    bank.clear_executors();
+}
+
+/// The accounts of the validator itself, which are updated in every slot and therefore
+/// excluded from the snapshots together with the sysvars.
+struct ValidatorAccounts {
+    identity: Pubkey,
+    vote_account: Pubkey,
+}
+
+impl ValidatorAccounts {
+    /// Returns `true` for the identity, the vote account and the stake accounts delegated
+    /// to the vote account of the validator and for the sysvars.
+    fn contains(&self, pubkey: &Pubkey, account: &AccountSharedData) -> bool {
+        *pubkey == self.identity
+            || *pubkey == self.vote_account
+            || *account.owner() == sysvar::id()
+            || (*account.owner() == stake::program::id()
+                && account
+                    .deserialize_data::<StakeState>()
+                    .ok()
+                    .and_then(|stake| stake.delegation())
+                    .map_or(false, |delegation| {
+                        delegation.voter_pubkey == self.vote_account
+                    }))
+    }
+}
+
+/// The accounts which differ from the snapshot with their captured state,
+/// the accounts created since the snapshot are returned as empty to be removed.
+fn changed_accounts(
+    snapshot: &ValidatorSnapshot,
+    accounts: impl IntoIterator<Item = (Pubkey, AccountSharedData)>,
+    validator_accounts: &ValidatorAccounts,
+) -> Vec<(Pubkey, AccountSharedData)> {
+    let mut current_accounts = HashSet::new();
+    let mut changed_accounts = vec![];
+    for (pubkey, account) in accounts {
+        if validator_accounts.contains(&pubkey, &account) {
+            continue;
+        }
+        current_accounts.insert(pubkey);
+        match snapshot.accounts.get(&pubkey) {
+            Some(captured) if accounts_equal(captured, &account) => {}
+            Some(captured) => changed_accounts.push((pubkey, captured.clone())),
+            // Accounts with zero lamports are removed
+            None => changed_accounts.push((pubkey, AccountSharedData::default())),
+        }
+    }
+    for (pubkey, captured) in snapshot.accounts.iter() {
+        if !current_accounts.contains(pubkey) {
+            changed_accounts.push((*pubkey, captured.clone()));
+        }
+    }
+    changed_accounts
 }
 
 impl Debug for Client {
@@ -61,13 +170,22 @@ impl Debug for Client {
         f.debug_struct("Client")
             .field("payer", &self.payer.pubkey())
             .field("rpc_url", &self.rpc_client.url())
-            .field("ledger_path", &self.ledger_path)
+            .field("ledger_path", &self.ledger.0)
             .finish()
     }
 }
 
 impl Client {
     pub fn new(payer: Keypair, test_validator: Arc<TestValidator>, ledger_path: PathBuf) -> Self {
+        Client::with_ledger(payer, test_validator, Arc::new(Ledger(ledger_path)))
+    }
+
+    /// Creates a client of the same validator sharing the ledger.
+    fn with_ledger(
+        payer: Keypair,
+        test_validator: Arc<TestValidator>,
+        ledger: Arc<Ledger>,
+    ) -> Self {
         Self {
             payer: payer.clone(),
             anchor_client: AnchorClient::new_with_options(
@@ -80,12 +198,12 @@ impl Client {
                 CommitmentConfig::confirmed(),
             ),
             test_validator,
-            ledger_path,
+            ledger,
         }
     }
 
     pub fn clone_with_payer(&self, payer: Keypair) -> Self {
-        Client::new(payer, self.test_validator.clone(), self.ledger_path.clone())
+        Client::with_ledger(payer, self.test_validator.clone(), self.ledger.clone())
     }
 
     /// Gets client's payer.
@@ -266,7 +384,8 @@ impl Client {
         .await
         .expect("send instruction task failed")?;
 
-        self.rpc_client
+        let transaction = self
+            .rpc_client
             .get_transaction_with_config(
                 &signature,
                 RpcTransactionConfig {
@@ -276,7 +395,58 @@ impl Client {
                 },
             )
             .await
-            .unwrap()
+            .unwrap();
+        log_compute_units(&transaction);
+        transaction
+    }
+
+    /// Sends the instruction expecting it to fail with a program error, e.g. a violated
+    /// Anchor constraint or `require!`, and returns the decoded error.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the instruction succeeds or fails with an error other than a program error.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let error = client
+    ///     .expect_error(turnstile::ID, turnstile::instruction::Coin {}, accounts, [])
+    ///     .await;
+    /// assert!(error.is(TurnstileError::Unauthorized));
+    /// ```
+    pub async fn expect_error(
+        &self,
+        program: Pubkey,
+        instruction: impl InstructionData + Send + 'static,
+        accounts: impl ToAccountMetas + Send + 'static,
+        signers: impl IntoIterator<Item = Keypair> + Send + 'static,
+    ) -> AnchorErrorInfo {
+        match self
+            .send_instruction(program, instruction, accounts, signers)
+            .await
+        {
+            Ok(_) => panic!("Expected the instruction to fail, but it succeeded"),
+            Err(e) => AnchorErrorInfo::from_client_error(&e)
+                .unwrap_or_else(|| panic!("The instruction failed with an unexpected error: {e}")),
+        }
+    }
+
+    /// Starts gathering the events emitted by the program in all the following transactions.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let collector = client.collect_events(turnstile::ID).await?;
+    /// // ... send the transactions
+    /// let events = collector
+    ///     .wait_for_events::<CoinInserted>(2, Duration::from_secs(5))
+    ///     .await;
+    /// assert_eq!(events.len(), 2);
+    /// ```
+    #[throws]
+    pub async fn collect_events(&self, program: Pubkey) -> EventCollector {
+        EventCollector::subscribe(self.test_validator.rpc_pubsub_url(), program).await?
     }
 
     /// Sends the transaction with associated instructions and signers.
@@ -327,7 +497,8 @@ impl Client {
         debug!("Sending transaction: {:?}", tx);
         let signature = self.rpc_client.send_and_confirm_transaction(tx).await?;
 
-        self.rpc_client
+        let transaction = self
+            .rpc_client
             .get_transaction_with_config(
                 &signature,
                 RpcTransactionConfig {
@@ -337,7 +508,103 @@ impl Client {
                 },
             ) // })
             .await
-            .expect("get transaction task failed")
+            .expect("get transaction task failed");
+        log_compute_units(&transaction);
+        transaction
+    }
+
+    /// Simulates the instruction without sending it, see [Client::simulate_transaction].
+    #[throws]
+    pub async fn simulate_instruction(
+        &self,
+        program: Pubkey,
+        instruction: impl InstructionData + Send,
+        accounts: impl ToAccountMetas + Send,
+        signers: impl IntoIterator<Item = Keypair> + Send,
+    ) -> SimulatedTransaction {
+        let instruction = Instruction {
+            program_id: program,
+            data: instruction.data(),
+            accounts: accounts.to_account_metas(None),
+        };
+        let signers = signers.into_iter().collect::<Vec<_>>();
+        self.simulate_transaction(&[instruction], signers.iter())
+            .await?
+    }
+
+    /// Simulates the transaction with associated instructions and signers without sending it.
+    ///
+    /// A failing transaction does not return an error, the error is stored in the result
+    /// together with the logs. The result contains all the accounts of the transaction
+    /// as they would be after the execution.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let simulation = client.simulate_transaction(&[instruction], [&state]).await?;
+    /// assert!(simulation.err.is_none());
+    /// assert!(simulation.units_consumed.unwrap() < 10_000);
+    /// ```
+    #[throws]
+    pub async fn simulate_transaction(
+        &self,
+        instructions: &[Instruction],
+        signers: impl IntoIterator<Item = &Keypair> + Send,
+    ) -> SimulatedTransaction {
+        let mut signers = signers.into_iter().collect::<Vec<_>>();
+        signers.push(self.payer());
+
+        let tx = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.payer.pubkey()),
+            &signers,
+            self.rpc_client
+                .get_latest_blockhash_with_commitment(CommitmentConfig::confirmed())
+                .await
+                .expect("Error while getting recent blockhash")
+                .0,
+        );
+        let addresses = tx.message.account_keys.clone();
+        debug!("Simulating transaction: {:?}", tx);
+        let result = self
+            .rpc_client
+            .simulate_transaction_with_config(
+                &tx,
+                RpcSimulateTransactionConfig {
+                    sig_verify: true,
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    accounts: Some(RpcSimulateTransactionAccountsConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        addresses: addresses.iter().map(ToString::to_string).collect(),
+                    }),
+                    ..RpcSimulateTransactionConfig::default()
+                },
+            )
+            .await?
+            .value;
+
+        let accounts = addresses
+            .into_iter()
+            .zip(result.accounts.unwrap_or_default())
+            .map(|(pubkey, account)| (pubkey, account.and_then(|account| account.decode())))
+            .collect();
+        let return_data = result.return_data.map(|return_data| {
+            (
+                return_data
+                    .program_id
+                    .parse()
+                    .expect("Invalid program id of the return data"),
+                base64::decode(return_data.data.0).expect("Invalid return data encoding"),
+            )
+        });
+        SimulatedTransaction {
+            err: result.err,
+            logs: result.logs.unwrap_or_default(),
+            return_data,
+            accounts,
+            units_consumed: result.units_consumed,
+        }
     }
 
     /// Airdrops lamports to the chosen account.
@@ -527,6 +794,176 @@ impl Client {
         debug!("program deployed");
     }
 
+    /// Deploys the program through the upgradeable loader (`bpf_loader_upgradeable`),
+    /// so it can be upgraded by the `upgrade_authority` later.
+    ///
+    /// The program data account is allocated for `max_data_len` bytes, twice the length
+    /// of the program by default, so bigger versions of the program fit in.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let program_data = Reader::new().program_data("turnstile").await?;
+    /// client
+    ///     .deploy_upgradeable(&program_keypair(0), &authority, &program_data, None)
+    ///     .await?;
+    /// ```
+    #[throws]
+    pub async fn deploy_upgradeable(
+        &self,
+        program_keypair: &Keypair,
+        upgrade_authority: &Keypair,
+        program_data: &[u8],
+        max_data_len: Option<usize>,
+    ) {
+        let buffer = Keypair::new();
+        self.write_buffer(&buffer, upgrade_authority, program_data)
+            .await?;
+
+        debug!("deploying upgradeable program {}", program_keypair.pubkey());
+        let program_lamports = self
+            .rpc_client
+            .get_minimum_balance_for_rent_exemption(UpgradeableLoaderState::size_of_program())
+            .await?;
+        let deploy_ixs = bpf_loader_upgradeable::deploy_with_max_program_len(
+            &self.payer.pubkey(),
+            &program_keypair.pubkey(),
+            &buffer.pubkey(),
+            &upgrade_authority.pubkey(),
+            program_lamports,
+            max_data_len.unwrap_or(program_data.len() * 2),
+        )
+        .expect("Unable to create deploy instructions");
+        self.send_transaction(&deploy_ixs, [program_keypair, upgrade_authority])
+            .await?;
+    }
+
+    /// Upgrades the program deployed by the upgradeable loader to the new program data.
+    ///
+    /// The lamports of the used buffer are returned to the payer.
+    #[throws]
+    pub async fn upgrade_program(
+        &self,
+        program: Pubkey,
+        upgrade_authority: &Keypair,
+        program_data: &[u8],
+    ) {
+        let buffer = Keypair::new();
+        self.write_buffer(&buffer, upgrade_authority, program_data)
+            .await?;
+
+        debug!("upgrading program {program}");
+        let upgrade_ix = bpf_loader_upgradeable::upgrade(
+            &program,
+            &buffer.pubkey(),
+            &upgrade_authority.pubkey(),
+            &self.payer.pubkey(),
+        );
+        self.send_transaction(&[upgrade_ix], [upgrade_authority])
+            .await?;
+    }
+
+    /// Creates the buffer account of the upgradeable loader and writes the program data into it.
+    ///
+    /// The buffer can be used to deploy or upgrade a program by the `authority`.
+    #[throws]
+    pub async fn write_buffer(&self, buffer: &Keypair, authority: &Keypair, program_data: &[u8]) {
+        const PROGRAM_DATA_CHUNK_SIZE: usize = 900;
+
+        debug!("creating buffer {}", buffer.pubkey());
+        let buffer_lamports = self
+            .rpc_client
+            .get_minimum_balance_for_rent_exemption(UpgradeableLoaderState::size_of_buffer(
+                program_data.len(),
+            ))
+            .await?;
+        let create_buffer_ixs = bpf_loader_upgradeable::create_buffer(
+            &self.payer.pubkey(),
+            &buffer.pubkey(),
+            &authority.pubkey(),
+            buffer_lamports,
+            program_data.len(),
+        )
+        .expect("Unable to create buffer instructions");
+        self.send_transaction(&create_buffer_ixs, [buffer]).await?;
+
+        debug!("writing program data to buffer");
+        let futures = program_data
+            .chunks(PROGRAM_DATA_CHUNK_SIZE)
+            .enumerate()
+            .map(|(index, chunk)| {
+                let write_ix = bpf_loader_upgradeable::write(
+                    &buffer.pubkey(),
+                    &authority.pubkey(),
+                    (index * PROGRAM_DATA_CHUNK_SIZE) as u32,
+                    chunk.to_vec(),
+                );
+                async move { self.send_transaction(&[write_ix], [authority]).await }
+            });
+        stream::iter(futures)
+            .buffer_unordered(100)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+    }
+
+    /// Sets the upgrade authority of the program, `None` makes the program immutable.
+    #[throws]
+    pub async fn set_upgrade_authority(
+        &self,
+        program: Pubkey,
+        current_authority: &Keypair,
+        new_authority: Option<Pubkey>,
+    ) {
+        let set_authority_ix = bpf_loader_upgradeable::set_upgrade_authority(
+            &program,
+            &current_authority.pubkey(),
+            new_authority.as_ref(),
+        );
+        self.send_transaction(&[set_authority_ix], [current_authority])
+            .await?;
+    }
+
+    /// Gets the upgrade authority of the program, `None` when the program is immutable.
+    #[throws]
+    pub async fn get_upgrade_authority(&self, program: Pubkey) -> Option<Pubkey> {
+        let (programdata_address, _) =
+            Pubkey::find_program_address(&[program.as_ref()], &bpf_loader_upgradeable::id());
+        match self.account_data_bincode(programdata_address).await? {
+            UpgradeableLoaderState::ProgramData {
+                upgrade_authority_address,
+                ..
+            } => upgrade_authority_address,
+            _ => throw!(Error::LogParseError(
+                "Invalid program data account".to_string()
+            )),
+        }
+    }
+
+    /// Closes the program and sends the lamports of its program data account to the recipient.
+    ///
+    /// A closed program cannot be invoked nor deployed again to the same address.
+    #[throws]
+    pub async fn close_program(&self, program: Pubkey, authority: &Keypair, recipient: Pubkey) {
+        let (programdata_address, _) =
+            Pubkey::find_program_address(&[program.as_ref()], &bpf_loader_upgradeable::id());
+        let close_ix = bpf_loader_upgradeable::close_any(
+            &programdata_address,
+            &recipient,
+            Some(&authority.pubkey()),
+            Some(&program),
+        );
+        self.send_transaction(&[close_ix], [authority]).await?;
+    }
+
+    /// Closes the buffer created by [Client::write_buffer] and sends its lamports to the recipient.
+    #[throws]
+    pub async fn close_buffer(&self, buffer: Pubkey, authority: &Keypair, recipient: Pubkey) {
+        let close_ix = bpf_loader_upgradeable::close(&buffer, &recipient, &authority.pubkey());
+        self.send_transaction(&[close_ix], [authority]).await?;
+    }
+
     /// Creates accounts.
     #[throws]
     pub async fn create_account(
@@ -713,32 +1150,243 @@ impl Client {
             offset += chunk.len();
         }
     }
-}
 
-impl Drop for Client {
-    fn drop(&mut self) {
-        self.ledger_path.exists().then(|| {
-            std::fs::remove_dir_all(&self.ledger_path).unwrap_or_else(|err| {
-                error!(
-                    "Error removing validator ledger {}: {}",
-                    self.ledger_path.display(),
-                    err
-                )
-            });
-        });
+    /// Captures all the accounts of the validator, including the programs, and the timestamp of its clock.
+    ///
+    /// Use [Client::restore] to return the validator to the captured state without restarting it.
+    pub async fn snapshot(&self) -> ValidatorSnapshot {
+        let bank = self.working_bank();
+        let validator_accounts = self.validator_accounts(&bank);
+        let accounts = bank
+            .get_all_accounts_with_modified_slots()
+            .expect("Unable to scan the accounts of the validator")
+            .into_iter()
+            .filter(|(pubkey, account, _)| !validator_accounts.contains(pubkey, account))
+            .map(|(pubkey, account, _)| (pubkey, account))
+            .collect();
+        ValidatorSnapshot {
+            accounts,
+            unix_timestamp: bank.clock().unix_timestamp,
+        }
+    }
+
+    /// Restores the accounts and the timestamp captured by [Client::snapshot].
+    ///
+    /// The accounts changed since the snapshot are overwritten and the created ones are removed.
+    /// The `unix_timestamp` of the clock is set back to the captured one and then follows the real
+    /// time again. The slot keeps increasing, a running validator cannot return to a previous slot.
+    pub async fn restore(&self, snapshot: &ValidatorSnapshot) {
+        // The scan is slow, it runs before the update so the bank is not kept from freezing
+        let changed_accounts = self.changed_accounts(snapshot);
+        debug!("restoring {} accounts", changed_accounts.len());
+        self.update_working_bank(|bank| {
+            store_accounts(bank, &changed_accounts);
+            let mut clock = bank.clock();
+            clock.unix_timestamp = snapshot.unix_timestamp;
+            bank.set_sysvar_for_tests(&clock);
+        })
+        .await
     }
+
+    /// The accounts which differ from the snapshot, see [changed_accounts].
+    fn changed_accounts(&self, snapshot: &ValidatorSnapshot) -> Vec<(Pubkey, AccountSharedData)> {
+        let bank = self.working_bank();
+        let accounts = bank
+            .get_all_accounts_with_modified_slots()
+            .expect("Unable to scan the accounts of the validator")
+            .into_iter()
+            .map(|(pubkey, account, _)| (pubkey, account));
+        changed_accounts(snapshot, accounts, &self.validator_accounts(&bank))
+    }
+
+    fn validator_accounts(&self, bank: &Bank) -> ValidatorAccounts {
+        ValidatorAccounts {
+            identity: *bank.collector_id(),
+            vote_account: self.test_validator.vote_account_address(),
+        }
+    }
+
+    /// Overwrites the account on the running validator without sending any transaction,
+    /// e.g. to craft corrupted or edge-case states no instruction would produce.
+    ///
+    /// All the fields of the account are set, including the owner and the `executable` flag.
+    /// An account with zero lamports is removed.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// let mut account = AccountSharedData::new(1_000_000, 8, &turnstile::ID);
+    /// account.set_data(vec![0xff; 8]);
+    /// client.set_account(state.pubkey(), account).await;
+    /// ```
+    pub async fn set_account(&self, pubkey: Pubkey, account: AccountSharedData) {
+        debug!("setting account {pubkey}");
+        self.update_working_bank(|bank| store_accounts(bank, &[(pubkey, account)]))
+            .await
+    }
+
+    /// Gets the [Clock] sysvar of the confirmed state.
+    #[throws]
+    pub async fn get_clock(&self) -> Clock {
+        self.account_data_bincode(sysvar::clock::id()).await?
+    }
+
+    /// Sets the `unix_timestamp` of the [Clock] sysvar, e.g. to test vesting or auctions.
+    ///
+    /// The validator keeps the timestamp at the set value until the real time catches up,
+    /// because it never moves the clock backwards. For the same reason, only a timestamp
+    /// in the future can be set.
+    ///
+    /// # Panics
+    ///
+    /// Panics when the timestamp is before the current one.
+    pub async fn set_unix_timestamp(&self, unix_timestamp: UnixTimestamp) {
+        self.update_clock(|clock| {
+            assert!(
+                unix_timestamp >= clock.unix_timestamp,
+                "The clock cannot be moved backwards from {} to {unix_timestamp}",
+                clock.unix_timestamp
+            );
+            clock.unix_timestamp = unix_timestamp;
+        })
+        .await
+    }
+
+    /// Moves the `unix_timestamp` of the [Clock] sysvar forward by the duration
+    /// rounded up to whole seconds, e.g. by 1 second for 1 millisecond.
+    ///
+    /// See [Client::set_unix_timestamp].
+    pub async fn advance_clock(&self, duration: Duration) {
+        self.update_clock(|clock| clock.unix_timestamp += whole_seconds(duration))
+            .await
+    }
+
+    /// Sets the timestamp of a just started validator set
+    /// by [Validator::unix_timestamp](crate::Validator::unix_timestamp). A timestamp before
+    /// the current one is ignored, because the validator never moves its clock backwards.
+    pub(crate) async fn start_clock_at(&self, unix_timestamp: UnixTimestamp) {
+        self.update_clock(|clock| clock.unix_timestamp = clock.unix_timestamp.max(unix_timestamp))
+            .await
+    }
+
+    async fn update_clock(&self, update: impl FnOnce(&mut Clock)) {
+        self.update_working_bank(|bank| {
+            let mut clock = bank.clock();
+            update(&mut clock);
+            debug!("setting unix timestamp to {}", clock.unix_timestamp);
+            // The next banks inherit the timestamp as it is later than their estimate
+            bank.set_sysvar_for_tests(&clock);
+        })
+        .await
+    }
+
+    fn working_bank(&self) -> Arc<Bank> {
+        self.test_validator
+            .bank_forks()
+            .read()
+            .unwrap()
+            .working_bank()
+    }
+
+    /// Runs the update on the working bank of the validator and waits until it is confirmed.
+    async fn update_working_bank<T>(&self, update: impl FnOnce(&Bank) -> T) -> T {
+        let mut update = Some(update);
+        let (slot, result) = loop {
+            let bank = self.working_bank();
+            let result = {
+                // The bank starts freezing under the write lock of `freeze_lock`,
+                // so it cannot start while the read lock is held
+                let _freeze_lock = bank.freeze_lock();
+                // Accounts cannot be stored to a bank which is being frozen, wait for the next one
+                (!bank.freeze_started()).then(|| (update.take().unwrap())(&bank))
+            };
+            match result {
+                Some(result) => break (bank.slot(), result),
+                None => time::sleep(Duration::from_millis(10)).await,
+            }
+        };
+        // The client reads the confirmed state, wait until it includes the changes
+        self.wait_for_slot(slot).await;
+        result
+    }
+
+    /// Waits until the confirmed slot of the validator reaches the slot,
+    /// returns immediately when the slot has already passed.
+    async fn wait_for_slot(&self, slot: Slot) {
+        while self
+            .rpc_client
+            .get_slot()
+            .await
+            .map_or(true, |confirmed_slot| confirmed_slot < slot)
+        {
+            time::sleep(Duration::from_millis(50)).await;
+        }
+    }
+}
+
+/// Converts the duration to seconds of the [Clock] sysvar, a started second is counted as a whole one.
+fn whole_seconds(duration: Duration) -> UnixTimestamp {
+    let seconds = duration.as_secs() + u64::from(duration.subsec_nanos() > 0);
+    seconds as UnixTimestamp
 }
 
 impl Clone for Client {
     fn clone(&self) -> Self {
-        Client::new(
+        Client::with_ledger(
             self.payer().clone(),
             self.test_validator.clone(),
-            self.ledger_path.clone(),
+            self.ledger.clone(),
         )
     }
 }
 
+/// The result of [Client::simulate_transaction].
+#[derive(Debug, Clone)]
+pub struct SimulatedTransaction {
+    /// The error of the transaction, `None` when the transaction would succeed.
+    pub err: Option<TransactionError>,
+    pub logs: Vec<String>,
+    /// The program which set the return data and the data.
+    pub return_data: Option<(Pubkey, Vec<u8>)>,
+    /// All the accounts of the transaction after the execution, `None` for non-existent accounts.
+    pub accounts: Vec<(Pubkey, Option<Account>)>,
+    pub units_consumed: Option<u64>,
+}
+
+impl SimulatedTransaction {
+    /// Returns the account of the transaction after the execution.
+    pub fn account(&self, pubkey: &Pubkey) -> Option<&Account> {
+        self.accounts
+            .iter()
+            .find(|(account_pubkey, _)| account_pubkey == pubkey)
+            .and_then(|(_, account)| account.as_ref())
+    }
+}
+
+/// Utility trait for reading the compute units consumed by executed transactions.
+pub trait ComputeUnits {
+    /// Compute units consumed by the transaction, `None` when they were not reported.
+    fn compute_units_consumed(&self) -> Option<u64>;
+}
+
+impl ComputeUnits for EncodedConfirmedTransactionWithStatusMeta {
+    fn compute_units_consumed(&self) -> Option<u64> {
+        self.transaction
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.compute_units_consumed.clone().into())
+    }
+}
+
+fn log_compute_units(transaction: &EncodedConfirmedTransactionWithStatusMeta) {
+    if let Some(units) = transaction.compute_units_consumed() {
+        debug!(
+            "transaction in slot {} consumed {} compute units",
+            transaction.slot, units
+        );
+    }
+}
+
 /// Utility trait for printing transaction results.
 pub trait PrintableTransaction {
     /// Pretty print the transaction results, tagged with the given name for distinguishability.
@@ -760,3 +1408,124 @@ impl PrintableTransaction for EncodedConfirmedTransactionWithStatusMeta {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_client::solana_sdk::{
+        account::WritableAccount,
+        stake::state::{Delegation, Meta, Stake},
+    };
+
+    fn account(lamports: u64, data: &[u8]) -> AccountSharedData {
+        let mut account = AccountSharedData::new(lamports, data.len(), &System::id());
+        account.set_data(data.to_vec());
+        account
+    }
+
+    fn stake_account(lamports: u64, voter_pubkey: Pubkey) -> AccountSharedData {
+        let stake = Stake {
+            delegation: Delegation {
+                voter_pubkey,
+                ..Delegation::default()
+            },
+            ..Stake::default()
+        };
+        AccountSharedData::new_data(
+            lamports,
+            &StakeState::Stake(Meta::default(), stake),
+            &stake::program::id(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_changed_accounts() {
+        let validator_accounts = ValidatorAccounts {
+            identity: Pubkey::new_unique(),
+            vote_account: Pubkey::new_unique(),
+        };
+        let validator_stake = Pubkey::new_unique();
+        let user_stake = Pubkey::new_unique();
+        let (changed, removed, created, unchanged) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+
+        // Snapshot
+        let accounts = vec![
+            (validator_accounts.identity, account(100, &[])),
+            (validator_accounts.vote_account, account(100, &[1])),
+            (
+                validator_stake,
+                stake_account(100, validator_accounts.vote_account),
+            ),
+            (user_stake, stake_account(100, Pubkey::new_unique())),
+            (
+                sysvar::clock::id(),
+                AccountSharedData::new(1, 1, &sysvar::id()),
+            ),
+            (changed, account(100, &[1, 2])),
+            (removed, account(100, &[])),
+            (unchanged, account(100, &[3])),
+        ];
+        let snapshot = ValidatorSnapshot {
+            accounts: accounts
+                .iter()
+                .filter(|(pubkey, account)| !validator_accounts.contains(pubkey, account))
+                .cloned()
+                .collect(),
+            unix_timestamp: 0,
+        };
+        assert_eq!(
+            snapshot.accounts.keys().collect::<HashSet<_>>(),
+            HashSet::from([&user_stake, &changed, &removed, &unchanged])
+        );
+
+        // Mutate
+        let mut current_accounts = accounts
+            .into_iter()
+            .filter(|(pubkey, _)| *pubkey != removed)
+            .map(|(pubkey, mut account)| {
+                if pubkey != unchanged {
+                    account.set_lamports(account.lamports() + 100);
+                }
+                (pubkey, account)
+            })
+            .collect::<HashMap<_, _>>();
+        current_accounts.insert(created, account(100, &[]));
+
+        // Restore
+        let restored = changed_accounts(&snapshot, current_accounts.clone(), &validator_accounts)
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        assert_eq!(
+            restored.keys().collect::<HashSet<_>>(),
+            HashSet::from([&user_stake, &changed, &removed, &created])
+        );
+        assert_eq!(restored[&created], AccountSharedData::default());
+        for pubkey in [user_stake, changed, removed] {
+            assert_eq!(restored[&pubkey], snapshot.accounts[&pubkey]);
+        }
+
+        // Nothing changes after the restore apart from the validator accounts
+        for (pubkey, account) in restored {
+            if account.lamports() == 0 {
+                current_accounts.remove(&pubkey);
+            } else {
+                current_accounts.insert(pubkey, account);
+            }
+        }
+        assert!(changed_accounts(&snapshot, current_accounts, &validator_accounts).is_empty());
+    }
+
+    #[test]
+    fn test_whole_seconds() {
+        assert_eq!(whole_seconds(Duration::ZERO), 0);
+        assert_eq!(whole_seconds(Duration::from_millis(1)), 1);
+        assert_eq!(whole_seconds(Duration::from_secs(60)), 60);
+        assert_eq!(whole_seconds(Duration::from_millis(60_500)), 61);
+    }
+}
@@ -214,6 +214,22 @@ impl Commander {
     /// It's used internally by the [`#[trdelnik_test]`](trdelnik_test::trdelnik_test) macro.
     #[throws]
     pub async fn generate_program_client_lib_rs(&self) {
+        let idl = self.program_idl().await?;
+        let use_tokens = self.parse_program_client_imports().await?;
+        let program_client = program_client_generator::generate_source_code(idl, &use_tokens);
+        let program_client = Self::format_program_code(&program_client).await?;
+
+        let rust_file_path = Path::new(self.root.as_ref())
+            .join(PROGRAM_CLIENT_DIRECTORY)
+            .join("src/lib.rs");
+        fs::write(rust_file_path, &program_client).await?;
+    }
+
+    /// Creates [Idl] from the expanded code of all the programs in the workspace.
+    ///
+    /// _Note_: The programs are expanded using `cargo +nightly rustc`.
+    #[throws]
+    pub async fn program_idl(&self) -> Idl {
         let idl_programs = self.program_packages().map(|package| async move {
             let name = package.name;
             let output = Command::new("cargo")
@@ -233,17 +249,9 @@ impl Commander {
                 Err(Error::ReadProgramCodeFailed(error_text))
             }
         });
-        let idl = Idl {
+        Idl {
             programs: try_join_all(idl_programs).await?,
-        };
-        let use_tokens = self.parse_program_client_imports().await?;
-        let program_client = program_client_generator::generate_source_code(idl, &use_tokens);
-        let program_client = Self::format_program_code(&program_client).await?;
-
-        let rust_file_path = Path::new(self.root.as_ref())
-            .join(PROGRAM_CLIENT_DIRECTORY)
-            .join("src/lib.rs");
-        fs::write(rust_file_path, &program_client).await?;
+        }
     }
 
     /// Formats program code.
@@ -3,6 +3,7 @@ extern crate lazy_static;
 use anyhow::Context;
 use fehler::throw;
 use serde::Deserialize;
+use solana_sdk::{fee_calculator::FeeRateGovernor, pubkey::Pubkey, rent::Rent};
 use std::{env, fs, io, path::PathBuf};
 use thiserror::Error;
 
@@ -41,32 +42,223 @@ impl From<_Test> for Test {
     }
 }
 
+/// A program loaded into the validator from a `.so` file.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ProgramFixture {
+    pub program_id: Pubkey,
+    /// Path relative to the root directory.
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct _ProgramFixture {
+    pub program_id: String,
+    pub path: PathBuf,
+}
+
+impl From<_ProgramFixture> for ProgramFixture {
+    fn from(_p: _ProgramFixture) -> Self {
+        Self {
+            program_id: _p
+                .program_id
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid program id of the fixture: {}", _p.program_id)),
+            path: _p.path,
+        }
+    }
+}
+
+/// Accounts and programs loaded into every validator, e.g. the programs the tested program
+/// depends on, so the tests do not need a connection to a cluster.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Fixtures {
+    /// Paths, relative to the root directory, of the accounts dumped by
+    /// `solana account --output json <ADDRESS>`.
+    pub accounts: Vec<PathBuf>,
+    pub programs: Vec<ProgramFixture>,
+}
+
+#[derive(Default, Debug, Deserialize, Clone)]
+struct _Fixtures {
+    #[serde(default)]
+    pub accounts: Option<Vec<PathBuf>>,
+    #[serde(default)]
+    pub programs: Option<Vec<_ProgramFixture>>,
+}
+
+impl From<_Fixtures> for Fixtures {
+    fn from(_f: _Fixtures) -> Self {
+        Self {
+            accounts: _f.accounts.unwrap_or_default(),
+            programs: _f
+                .programs
+                .unwrap_or_default()
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+        }
+    }
+}
+
+/// Runtime settings of every validator, e.g. to reproduce mainnet-like conditions.
+/// The settings which are not set keep the defaults of the test validator.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ValidatorConfig {
+    /// Features deactivated in the genesis, all the other features are active.
+    pub deactivate_features: Vec<Pubkey>,
+    /// Features activated in the genesis, e.g. to override a deactivation.
+    pub activate_features: Vec<Pubkey>,
+    pub compute_unit_limit: Option<u64>,
+    pub ticks_per_slot: Option<u64>,
+    pub slots_per_epoch: Option<u64>,
+    pub rent: Option<Rent>,
+    pub fee_rate_governor: Option<FeeRateGovernor>,
+}
+
+#[derive(Default, Debug, Deserialize, Clone)]
+struct _ValidatorConfig {
+    #[serde(default)]
+    pub deactivate_features: Option<Vec<String>>,
+    #[serde(default)]
+    pub activate_features: Option<Vec<String>>,
+    #[serde(default)]
+    pub compute_unit_limit: Option<u64>,
+    #[serde(default)]
+    pub ticks_per_slot: Option<u64>,
+    #[serde(default)]
+    pub slots_per_epoch: Option<u64>,
+    #[serde(default)]
+    pub rent: Option<_Rent>,
+    #[serde(default)]
+    pub fee_rate_governor: Option<_FeeRateGovernor>,
+}
+
+impl From<_ValidatorConfig> for ValidatorConfig {
+    fn from(_v: _ValidatorConfig) -> Self {
+        Self {
+            deactivate_features: parse_features(_v.deactivate_features),
+            activate_features: parse_features(_v.activate_features),
+            compute_unit_limit: _v.compute_unit_limit,
+            ticks_per_slot: _v.ticks_per_slot,
+            slots_per_epoch: _v.slots_per_epoch,
+            rent: _v.rent.map(Into::into),
+            fee_rate_governor: _v.fee_rate_governor.map(Into::into),
+        }
+    }
+}
+
+fn parse_features(features: Option<Vec<String>>) -> Vec<Pubkey> {
+    features
+        .unwrap_or_default()
+        .iter()
+        .map(|feature| {
+            feature
+                .parse()
+                .unwrap_or_else(|_| panic!("invalid feature id: {feature}"))
+        })
+        .collect()
+}
+
+#[derive(Default, Debug, Deserialize, Clone)]
+struct _Rent {
+    #[serde(default)]
+    pub lamports_per_byte_year: Option<u64>,
+    #[serde(default)]
+    pub exemption_threshold: Option<f64>,
+    #[serde(default)]
+    pub burn_percent: Option<u8>,
+}
+
+impl From<_Rent> for Rent {
+    fn from(_r: _Rent) -> Self {
+        let default = Rent::default();
+        Self {
+            lamports_per_byte_year: _r
+                .lamports_per_byte_year
+                .unwrap_or(default.lamports_per_byte_year),
+            exemption_threshold: _r
+                .exemption_threshold
+                .unwrap_or(default.exemption_threshold),
+            burn_percent: _r.burn_percent.unwrap_or(default.burn_percent),
+        }
+    }
+}
+
+#[derive(Default, Debug, Deserialize, Clone)]
+struct _FeeRateGovernor {
+    #[serde(default)]
+    pub target_lamports_per_signature: Option<u64>,
+    #[serde(default)]
+    pub target_signatures_per_slot: Option<u64>,
+    #[serde(default)]
+    pub burn_percent: Option<u8>,
+}
+
+impl From<_FeeRateGovernor> for FeeRateGovernor {
+    fn from(_f: _FeeRateGovernor) -> Self {
+        let default = FeeRateGovernor::default();
+        let mut fee_rate_governor = FeeRateGovernor::new(
+            _f.target_lamports_per_signature
+                .unwrap_or(default.target_lamports_per_signature),
+            _f.target_signatures_per_slot
+                .unwrap_or(default.target_signatures_per_slot),
+        );
+        fee_rate_governor.burn_percent = _f.burn_percent.unwrap_or(default.burn_percent);
+        fee_rate_governor
+    }
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub test: Test,
+    pub fixtures: Fixtures,
+    pub validator: ValidatorConfig,
 }
 
 #[derive(Default, Debug, Deserialize, Clone)]
 struct _Config {
     #[serde(default)]
     pub test: Option<_Test>,
+    #[serde(default)]
+    pub fixtures: Option<_Fixtures>,
+    #[serde(default)]
+    pub validator: Option<_ValidatorConfig>,
 }
 
 impl From<_Config> for Config {
     fn from(_c: _Config) -> Self {
         Self {
             test: _c.test.unwrap_or_default().into(),
+            fixtures: _c.fixtures.unwrap_or_default().into(),
+            validator: _c.validator.unwrap_or_default().into(),
         }
     }
 }
 
+impl Default for Config {
+    fn default() -> Self {
+        _Config::default().into()
+    }
+}
+
 impl Config {
+    /// Loads `Trdelnik.toml` from the root directory, the defaults are used when there is
+    /// no root directory or no config file, e.g. when a test runs outside of a Trdelnik workspace.
     pub fn new() -> Self {
-        let root = Config::discover_root().expect("failed to find the root folder");
-        let s = fs::read_to_string(root.join(TRDELNIK_TOML).as_path())
-            .expect("failed to read the Trdelnik config file");
-        let _config: _Config =
-            toml::from_str(&s).expect("failed to parse the Trdelnik config file");
+        let path = match Config::discover_root() {
+            Ok(root) => root.join(TRDELNIK_TOML),
+            Err(_) => return Config::default(),
+        };
+        if !path.exists() {
+            return Config::default();
+        }
+        let s =
+            fs::read_to_string(path.as_path()).expect("failed to read the Trdelnik config file");
+        Config::parse(&s)
+    }
+
+    fn parse(s: &str) -> Self {
+        let _config: _Config = toml::from_str(s).expect("failed to parse the Trdelnik config file");
         _config.into()
     }
 
@@ -99,3 +291,101 @@ impl Config {
 lazy_static::lazy_static! {
     pub static ref CONFIG: Config = Config::new();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_defaults() {
+        let config = Config::parse("");
+        assert_eq!(config.test.validator_startup_timeout, 10_000);
+        assert!(config.fixtures.accounts.is_empty());
+        assert!(config.fixtures.programs.is_empty());
+        assert!(config.validator.deactivate_features.is_empty());
+        assert!(config.validator.activate_features.is_empty());
+        assert_eq!(config.validator.compute_unit_limit, None);
+        assert_eq!(config.validator.rent, None);
+        assert_eq!(config.validator.fee_rate_governor, None);
+    }
+
+    #[test]
+    fn test_parse_fixtures() {
+        let config = Config::parse(
+            r#"
+            [fixtures]
+            accounts = ["fixtures/pyth_price.json"]
+
+            [[fixtures.programs]]
+            program_id = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb"
+            path = "fixtures/spl_token_2022.so"
+            "#,
+        );
+        assert_eq!(
+            config.fixtures.accounts,
+            [PathBuf::from("fixtures/pyth_price.json")]
+        );
+        assert_eq!(config.fixtures.programs.len(), 1);
+        assert_eq!(
+            config.fixtures.programs[0].program_id.to_string(),
+            "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb"
+        );
+        assert_eq!(
+            config.fixtures.programs[0].path,
+            PathBuf::from("fixtures/spl_token_2022.so")
+        );
+    }
+
+    #[test]
+    fn test_parse_validator() {
+        let config = Config::parse(
+            r#"
+            [validator]
+            deactivate_features = ["EfhYd3SafzGT472tYQDUc4dPd2xdEfKs5fwkowUgVt4W"]
+            activate_features = ["3E3jV7v9VcdJL8iYZUMax9DiDno8j7EWUVbhm9RtShj2"]
+            compute_unit_limit = 200000
+            ticks_per_slot = 64
+            slots_per_epoch = 432000
+
+            [validator.rent]
+            lamports_per_byte_year = 1000
+            exemption_threshold = 1.0
+
+            [validator.fee_rate_governor]
+            target_lamports_per_signature = 5000
+            burn_percent = 100
+            "#,
+        );
+        let validator = config.validator;
+        assert_eq!(
+            validator.deactivate_features,
+            ["EfhYd3SafzGT472tYQDUc4dPd2xdEfKs5fwkowUgVt4W"
+                .parse::<Pubkey>()
+                .unwrap()]
+        );
+        assert_eq!(
+            validator.activate_features,
+            ["3E3jV7v9VcdJL8iYZUMax9DiDno8j7EWUVbhm9RtShj2"
+                .parse::<Pubkey>()
+                .unwrap()]
+        );
+        assert_eq!(validator.compute_unit_limit, Some(200_000));
+        assert_eq!(validator.ticks_per_slot, Some(64));
+        assert_eq!(validator.slots_per_epoch, Some(432_000));
+
+        // The values left out keep their defaults
+        let rent = validator.rent.unwrap();
+        assert_eq!(rent.lamports_per_byte_year, 1000);
+        assert_eq!(rent.exemption_threshold, 1.0);
+        assert_eq!(rent.burn_percent, Rent::default().burn_percent);
+
+        let fee_rate_governor = validator.fee_rate_governor.unwrap();
+        let default = FeeRateGovernor::default();
+        assert_eq!(fee_rate_governor.target_lamports_per_signature, 5000);
+        assert_eq!(
+            fee_rate_governor.target_signatures_per_slot,
+            default.target_signatures_per_slot
+        );
+        assert_eq!(fee_rate_governor.burn_percent, 100);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::SimulatedTransaction;
+use anchor_client::{
+    anchor_lang::Event,
+    solana_client::rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter},
+    solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey},
+    ClientError,
+};
+use fehler::throws;
+use futures::StreamExt;
+use log::debug;
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_transaction_status::EncodedConfirmedTransactionWithStatusMeta;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::{sync::oneshot, task::JoinHandle, time};
+
+const PROGRAM_DATA_PREFIX: &str = "Program data: ";
+const POLL_EVENTS_EVERY_MILLIS: u64 = 100;
+
+/// Decodes the events of the type `E` emitted by `emit!` from the transaction logs.
+///
+/// The events are matched by their discriminator, the other `Program data:` lines are skipped.
+pub fn decode_events<E: Event>(logs: &[String]) -> Vec<E> {
+    logs.iter()
+        .filter_map(|log| log.strip_prefix(PROGRAM_DATA_PREFIX))
+        .filter_map(|data| base64::decode(data).ok())
+        .filter(|data| data.starts_with(&E::DISCRIMINATOR))
+        .filter_map(
+            |data| match E::deserialize(&mut &data[E::DISCRIMINATOR.len()..]) {
+                Ok(event) => Some(event),
+                Err(e) => {
+                    debug!("cannot deserialize the event: {e}");
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Access to the Anchor events emitted during the transaction.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let transaction = turnstile_instruction::coin(&client, /* ... */).await?;
+/// let events = transaction.events::<CoinInserted>();
+/// ```
+pub trait TransactionEvents {
+    /// The events of the type `E` in the order they were emitted.
+    fn events<E: Event>(&self) -> Vec<E>;
+}
+
+impl TransactionEvents for EncodedConfirmedTransactionWithStatusMeta {
+    fn events<E: Event>(&self) -> Vec<E> {
+        let logs: Option<Vec<String>> = self
+            .transaction
+            .meta
+            .as_ref()
+            .and_then(|meta| meta.log_messages.clone().into());
+        decode_events(&logs.unwrap_or_default())
+    }
+}
+
+impl TransactionEvents for SimulatedTransaction {
+    fn events<E: Event>(&self) -> Vec<E> {
+        decode_events(&self.logs)
+    }
+}
+
+/// Gathers the logs of all the successful transactions mentioning the program
+/// so the events emitted during the whole test can be checked at once.
+///
+/// The collector is created by [Client::collect_events](crate::Client::collect_events)
+/// and stops listening when dropped. The logs are delivered by the validator asynchronously,
+/// use [EventCollector::wait_for_events] to wait for the events of the just sent transactions.
+pub struct EventCollector {
+    logs: Arc<Mutex<Vec<String>>>,
+    task: JoinHandle<()>,
+}
+
+impl EventCollector {
+    #[throws(ClientError)]
+    pub(crate) async fn subscribe(pubsub_url: String, program: Pubkey) -> Self {
+        let logs = Arc::new(Mutex::new(vec![]));
+        let (ready_sender, ready_receiver) = oneshot::channel();
+
+        let task_logs = logs.clone();
+        let task = tokio::spawn(async move {
+            let pubsub_client = match PubsubClient::new(&pubsub_url).await {
+                Ok(pubsub_client) => pubsub_client,
+                Err(e) => {
+                    let _ = ready_sender.send(Err(e));
+                    return;
+                }
+            };
+            let subscription = pubsub_client
+                .logs_subscribe(
+                    RpcTransactionLogsFilter::Mentions(vec![program.to_string()]),
+                    RpcTransactionLogsConfig {
+                        commitment: Some(CommitmentConfig::confirmed()),
+                    },
+                )
+                .await;
+            let mut notifications = match subscription {
+                Ok((notifications, _unsubscribe)) => notifications,
+                Err(e) => {
+                    let _ = ready_sender.send(Err(e));
+                    return;
+                }
+            };
+            let _ = ready_sender.send(Ok(()));
+
+            while let Some(response) = notifications.next().await {
+                // The changes of failed transactions are reverted, so are their events
+                if response.value.err.is_none() {
+                    task_logs.lock().unwrap().extend(response.value.logs);
+                }
+            }
+        });
+
+        ready_receiver
+            .await
+            .expect("event subscription task failed")?;
+        Self { logs, task }
+    }
+
+    /// The events of the type `E` gathered so far in the order they were emitted.
+    pub fn events<E: Event>(&self) -> Vec<E> {
+        decode_events(&self.logs.lock().unwrap())
+    }
+
+    /// Waits until at least `count` events of the type `E` are gathered or the timeout expires
+    /// and returns all the gathered events of the type.
+    pub async fn wait_for_events<E: Event>(&self, count: usize, timeout: Duration) -> Vec<E> {
+        let start = Instant::now();
+        loop {
+            let events = self.events::<E>();
+            if events.len() >= count || start.elapsed() >= timeout {
+                return events;
+            }
+            time::sleep(Duration::from_millis(POLL_EVENTS_EVERY_MILLIS)).await;
+        }
+    }
+
+    /// Forgets all the gathered events.
+    pub fn clear(&self) {
+        self.logs.lock().unwrap().clear();
+    }
+}
+
+impl Drop for EventCollector {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
@@ -0,0 +1,442 @@
+use std::collections::HashMap;
+
+use crate::idl::{Idl, IdlAccountKind, IdlAccountMeta};
+use quote::{format_ident, ToTokens};
+use syn::{parse_quote, parse_str};
+
+/// Types implementing `trdelnik_fuzz::Arbitrary`, the instruction parameters of these types
+/// are generated by the fuzzer, the other ones have to be filled in manually.
+const ARBITRARY_TYPES: &[&str] = &[
+    "u8", "u16", "u32", "u64", "u128", "i8", "i16", "i32", "i64", "i128", "bool", "f32", "f64",
+    "Pubkey", "String", "Vec", "Option",
+];
+
+/// Name of the macro marking the values which cannot be inferred from [Idl], the macro calls
+/// are replaced by `TODO` comments and default values in the generated code,
+/// see [expand_todo_comments].
+const TODO_MACRO: &str = "__trdelnik_todo";
+
+/// Generates the skeleton of a fuzz test from [Idl] created from Anchor programs.
+///
+/// The fuzz test contains one flow per instruction calling the `program_client` function
+/// of the instruction with the parameters generated by the fuzzer, an init handler stub, an expected state and a validator initializer
+/// adding all the programs.
+///
+/// The generated fuzz test is runnable. Signers are filled in with the payer, the `System` program
+/// and the programs of [Idl] with their ids and the other accounts with the keypairs stored in the expected state,
+/// the accounts created by an instruction sign it. The values which cannot be inferred,
+/// e.g. PDAs, are set to their defaults and marked by `TODO` comments.
+///
+/// _Note_: See the crate's tests for output example.
+pub fn generate_source_code(idl: Idl) -> String {
+    let prefix_names = idl.programs.len() > 1;
+    let program_modules = idl
+        .programs
+        .iter()
+        .map(|idl_program| {
+            (
+                idl_program.name.upper_camel_case.clone(),
+                idl_program.name.snake_case.replace('-', "_"),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let mut use_items: Vec<syn::ItemUse> = vec![];
+    let mut add_programs: Vec<syn::Stmt> = vec![];
+    let mut parameter_structs: Vec<syn::ItemStruct> = vec![];
+    let mut flows: Vec<syn::ItemFn> = vec![];
+    let mut state_keypairs: Vec<syn::Ident> = vec![];
+    let mut imports = Imports::default();
+
+    for idl_program in idl.programs {
+        let program_name = idl_program.name.snake_case.replace('-', "_");
+        let instruction_module_name = format_ident!("{}_instruction", program_name);
+        let module_name: syn::Ident = parse_str(&program_name).unwrap();
+
+        use_items.push(parse_quote! { use program_client::#instruction_module_name; });
+        add_programs.push(parse_quote! {
+            validator.add_program(#program_name, #instruction_module_name::PROGRAM_ID);
+        });
+
+        for (idl_instruction, idl_account_group) in idl_program.instruction_account_pairs {
+            let instruction_fn_name: syn::Ident =
+                parse_str(&idl_instruction.name.snake_case).unwrap();
+            let instruction_struct_name: syn::Ident =
+                parse_str(&idl_instruction.name.upper_camel_case).unwrap();
+            let flow_name = if prefix_names {
+                format_ident!("flow_{}_{}", program_name, idl_instruction.name.snake_case)
+            } else {
+                format_ident!("flow_{}", idl_instruction.name.snake_case)
+            };
+
+            // The parameters generated by the fuzzer are collected into a struct deriving
+            // `Arbitrary`, because it cannot be derived for the instruction structs of Anchor
+            let parameters_struct_name =
+                format_ident!("{}Parameters", idl_instruction.name.upper_camel_case);
+            let mut arbitrary_parameters: Vec<(syn::Ident, syn::Type)> = vec![];
+            let parameters = idl_instruction
+                .parameters
+                .iter()
+                .map(|(name, ty)| {
+                    let name: syn::Ident = parse_str(name).unwrap();
+                    let ty: syn::Type = parse_str(ty).unwrap();
+                    if is_arbitrary(&ty) {
+                        arbitrary_parameters.push((name.clone(), ty));
+                        parse_quote! { #name: parameters.#name }
+                    } else {
+                        todo_field_value(&name, &format!("set the `{name}` parameter"))
+                    }
+                })
+                .collect::<Vec<syn::FieldValue>>();
+            let mut inputs: Vec<syn::FnArg> = vec![];
+            if !arbitrary_parameters.is_empty() {
+                let (names, types): (Vec<_>, Vec<_>) = arbitrary_parameters.into_iter().unzip();
+                parameter_structs.push(parse_quote! {
+                    #[derive(Debug, Clone, Arbitrary)]
+                    struct #parameters_struct_name {
+                        #(#names: #types),*
+                    }
+                });
+                inputs.push(parse_quote! { Input(parameters): Input<#parameters_struct_name> });
+                imports.input = true;
+            }
+
+            let mut accounts = AccountsGenerator {
+                module_name: &module_name,
+                program_name: prefix_names.then_some(program_name.as_str()),
+                program_modules: &program_modules,
+                account_metas: &idl_account_group.account_metas,
+                nested_groups: &idl_account_group.nested_groups,
+                state_keypairs: &mut state_keypairs,
+                signers: vec![],
+                uses_state: false,
+                imports: &mut imports,
+            };
+            let accounts_struct =
+                accounts.accounts_struct("", &idl_account_group.name.upper_camel_case);
+            let signers = &accounts.signers;
+            let signers: syn::Expr = if signers.is_empty() {
+                parse_quote! { None }
+            } else {
+                parse_quote! { [#(expected_state.#signers.insecure_clone()),*] }
+            };
+            let state_binding = if accounts.uses_state {
+                format_ident!("expected_state")
+            } else {
+                format_ident!("_expected_state")
+            };
+
+            flows.push(parse_quote! {
+                async fn #flow_name(
+                    client: Client,
+                    State(#state_binding): State<ExpectedState>,
+                    #(#inputs),*
+                ) -> Result<(), ClientError> {
+                    #instruction_module_name::#instruction_fn_name(
+                        &client,
+                        #module_name::instruction::#instruction_struct_name {
+                            #(#parameters),*
+                        },
+                        #accounts_struct,
+                        #signers,
+                    )
+                    .await?;
+                    Ok(())
+                }
+            });
+        }
+    }
+
+    use_items.push(imports.client_imports());
+    use_items.push(imports.fuzz_imports());
+    if !state_keypairs.is_empty() {
+        use_items.insert(0, parse_quote! { use std::sync::Arc; });
+    }
+
+    let expected_state: syn::ItemStruct = parse_quote! {
+        #[derive(Clone, Debug)]
+        struct ExpectedState {
+            #(#state_keypairs: Arc<Keypair>),*
+        }
+    };
+
+    let initialize_validator: syn::ItemFn = parse_quote! {
+        fn initialize_validator() -> Validator {
+            let mut validator = Validator::default();
+            #(#add_programs)*
+            validator
+        }
+    };
+
+    let todo_message = "initialize the programs and the expected state";
+    let todo_macro = format_ident!("{}", TODO_MACRO);
+    let init_handler: syn::ItemFn = parse_quote! {
+        async fn init_handler(
+            _client: Client,
+            State(_expected_state): State<ExpectedState>,
+        ) -> Result<(), ClientError> {
+            #todo_macro!(#todo_message);
+            Ok(())
+        }
+    };
+
+    let flow_names = flows.iter().map(|flow| &flow.sig.ident);
+    let main: syn::ItemFn = parse_quote! {
+        #[trdelnik_fuzz]
+        async fn main() {
+            FuzzTestBuilder::new()
+                .initialize_validator(initialize_validator)
+                .add_init_handler(init_handler)
+                #(.add_flow(#flow_names))*
+                .with_state(ExpectedState {
+                    #(#state_keypairs: Arc::new(Keypair::new())),*
+                })
+                .start(2, 200)
+                .await;
+        }
+    };
+
+    let imports = use_items
+        .iter()
+        .map(|item| item.to_token_stream().to_string())
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut items = vec![imports, expected_state.into_token_stream().to_string()];
+    items.extend(
+        parameter_structs
+            .iter()
+            .map(|item| item.to_token_stream().to_string()),
+    );
+    items.extend([
+        initialize_validator.into_token_stream().to_string(),
+        init_handler.into_token_stream().to_string(),
+    ]);
+    items.extend(flows.iter().map(|flow| flow.to_token_stream().to_string()));
+    items.push(main.into_token_stream().to_string());
+    // The items are separated by empty lines, which are kept by rustfmt
+    expand_todo_comments(&(items.join("\n\n") + "\n"))
+}
+
+/// Items imported from `trdelnik_client` and `trdelnik_fuzz` depending on their usage.
+#[derive(Default)]
+struct Imports {
+    input: bool,
+    keypair: bool,
+    signer: bool,
+    system: bool,
+}
+
+impl Imports {
+    fn client_imports(&self) -> syn::ItemUse {
+        // `tokio` and `FutureExt` are used by the expansion of the `trdelnik_fuzz` attribute
+        let mut items = vec![
+            format_ident!("tokio"),
+            format_ident!("trdelnik_fuzz"),
+            format_ident!("Client"),
+            format_ident!("ClientError"),
+            format_ident!("FutureExt"),
+        ];
+        if self.system {
+            items.push(format_ident!("Id"));
+        }
+        if self.keypair {
+            items.push(format_ident!("Keypair"));
+        }
+        if self.signer {
+            items.push(format_ident!("Signer"));
+        }
+        if self.system {
+            items.push(format_ident!("System"));
+        }
+        items.push(format_ident!("Validator"));
+        parse_quote! { use trdelnik_client::{#(#items),*}; }
+    }
+
+    fn fuzz_imports(&self) -> syn::ItemUse {
+        if self.input {
+            parse_quote! { use trdelnik_fuzz::{Arbitrary, FuzzTestBuilder, Input, State}; }
+        } else {
+            parse_quote! { use trdelnik_fuzz::{FuzzTestBuilder, State}; }
+        }
+    }
+}
+
+/// Fills in the accounts of an instruction, the nested account groups included.
+struct AccountsGenerator<'a> {
+    module_name: &'a syn::Ident,
+    /// Prefix of the expected state fields when there are multiple programs.
+    program_name: Option<&'a str>,
+    /// Names of the program modules by the names of the program types.
+    program_modules: &'a HashMap<String, String>,
+    account_metas: &'a [IdlAccountMeta],
+    nested_groups: &'a [(String, String)],
+    /// Keypairs stored in the expected state, shared by all instructions.
+    state_keypairs: &'a mut Vec<syn::Ident>,
+    /// Keypairs of the accounts created by the instruction.
+    signers: Vec<syn::Ident>,
+    uses_state: bool,
+    imports: &'a mut Imports,
+}
+
+impl AccountsGenerator<'_> {
+    /// Generates the accounts struct of the group, the names of the accounts of nested groups
+    /// are prefixed by `prefix` like in [IdlAccountMeta::name].
+    fn accounts_struct(&mut self, prefix: &str, struct_name: &str) -> syn::ExprStruct {
+        let module_name = self.module_name;
+        let struct_name: syn::Ident = parse_str(struct_name).unwrap();
+        let mut fields: Vec<syn::FieldValue> = vec![];
+        for account_meta in self.account_metas {
+            let field_name = match direct_child(&account_meta.name, prefix) {
+                Some(field_name) => field_name,
+                None => continue,
+            };
+            let field_name: syn::Ident = parse_str(field_name).unwrap();
+            fields.push(self.account_field(&field_name, account_meta));
+        }
+        for (nested_group, nested_struct_name) in self.nested_groups {
+            let field_name = match direct_child(nested_group, prefix) {
+                Some(field_name) => field_name,
+                None => continue,
+            };
+            let field_name: syn::Ident = parse_str(field_name).unwrap();
+            let nested_struct =
+                self.accounts_struct(&format!("{nested_group}."), nested_struct_name);
+            fields.push(parse_quote! { #field_name: #nested_struct });
+        }
+        parse_quote! {
+            #module_name::accounts::#struct_name {
+                #(#fields),*
+            }
+        }
+    }
+
+    fn account_field(
+        &mut self,
+        field_name: &syn::Ident,
+        account_meta: &IdlAccountMeta,
+    ) -> syn::FieldValue {
+        match &account_meta.kind {
+            IdlAccountKind::Signer => {
+                self.imports.signer = true;
+                parse_quote! { #field_name: client.payer().pubkey() }
+            }
+            IdlAccountKind::Program(program) if program == "System" => {
+                self.imports.system = true;
+                parse_quote! { #field_name: System::id() }
+            }
+            IdlAccountKind::Program(program) => match self.program_modules.get(program) {
+                Some(program_module) => {
+                    let program_module: syn::Ident = parse_str(program_module).unwrap();
+                    parse_quote! { #field_name: #program_module::ID }
+                }
+                None => todo_field_value(
+                    field_name,
+                    &format!("set the id of the `{program}` program"),
+                ),
+            },
+            IdlAccountKind::Address(address) => todo_field_value(
+                field_name,
+                &format!("set the `{}` account to `{address}`", account_meta.name),
+            ),
+            IdlAccountKind::Pda => todo_field_value(
+                field_name,
+                &format!("derive the `{}` PDA", account_meta.name),
+            ),
+            IdlAccountKind::Created | IdlAccountKind::Other => {
+                let keypair = self.state_keypair(&account_meta.name);
+                if account_meta.kind == IdlAccountKind::Created {
+                    self.signers.push(keypair.clone());
+                }
+                self.uses_state = true;
+                self.imports.signer = true;
+                parse_quote! { #field_name: expected_state.#keypair.pubkey() }
+            }
+        }
+    }
+
+    /// Returns the expected state field with the keypair of the account, the field is added
+    /// when the account is used for the first time.
+    fn state_keypair(&mut self, account_name: &str) -> syn::Ident {
+        let account_name = account_name.replace('.', "_");
+        let keypair = match self.program_name {
+            Some(program_name) => format_ident!("{}_{}", program_name, account_name),
+            None => format_ident!("{}", account_name),
+        };
+        if !self.state_keypairs.contains(&keypair) {
+            self.state_keypairs.push(keypair.clone());
+        }
+        self.imports.keypair = true;
+        keypair
+    }
+}
+
+/// Returns the name of the account or nested group if it's a direct child of the group
+/// with the `prefix`.
+fn direct_child<'a>(name: &'a str, prefix: &str) -> Option<&'a str> {
+    name.strip_prefix(prefix)
+        .filter(|field_name| !field_name.contains('.'))
+}
+
+/// Field with a value which cannot be inferred, see [expand_todo_comments].
+fn todo_field_value(field_name: &syn::Ident, message: &str) -> syn::FieldValue {
+    let todo_macro = format_ident!("{}", TODO_MACRO);
+    parse_quote! { #field_name: #todo_macro!(#message) }
+}
+
+/// Replaces the [TODO_MACRO] calls by `TODO` comments, e.g. `state : __trdelnik_todo ! ("msg")`
+/// by `// TODO: msg` followed by `state : Default :: default ()` and the statement
+/// `__trdelnik_todo ! ("msg") ;` only by the comment.
+fn expand_todo_comments(code: &str) -> String {
+    let marker = format!("{TODO_MACRO} ! (\"");
+    let mut expanded = String::with_capacity(code.len());
+    let mut rest = code;
+    while let Some(start) = rest.find(&marker) {
+        let (before, call) = rest.split_at(start);
+        let call = &call[marker.len()..];
+        let end = call.find("\")").expect("unterminated TODO marker");
+        let message = &call[..end];
+        rest = &call[end + 2..];
+        match before.strip_suffix(" : ") {
+            // The field value, the comment is put above the field
+            Some(before) => {
+                let field_start = before
+                    .rfind(|c: char| !(c.is_alphanumeric() || c == '_'))
+                    .map_or(0, |index| index + 1);
+                let (before, field_name) = before.split_at(field_start);
+                expanded.push_str(before);
+                expanded.push_str(&format!(
+                    "\n// TODO: {message}\n{field_name} : Default :: default ()"
+                ));
+            }
+            // The statement
+            None => {
+                expanded.push_str(before);
+                expanded.push_str(&format!("\n// TODO: {message}\n"));
+                rest = rest.strip_prefix(" ;").unwrap_or(rest);
+            }
+        }
+    }
+    expanded.push_str(rest);
+    expanded
+}
+
+/// Returns `true` when the type is built only from [ARBITRARY_TYPES], arrays and tuples.
+fn is_arbitrary(ty: &syn::Type) -> bool {
+    match ty {
+        syn::Type::Path(type_path) => type_path.path.segments.last().map_or(false, |segment| {
+            ARBITRARY_TYPES.contains(&segment.ident.to_string().as_str())
+                && match &segment.arguments {
+                    syn::PathArguments::None => true,
+                    syn::PathArguments::AngleBracketed(arguments) => {
+                        arguments.args.iter().all(|argument| match argument {
+                            syn::GenericArgument::Type(ty) => is_arbitrary(ty),
+                            _ => false,
+                        })
+                    }
+                    syn::PathArguments::Parenthesized(_) => false,
+                }
+        }),
+        syn::Type::Array(array) => is_arbitrary(&array.elem),
+        syn::Type::Tuple(tuple) => !tuple.elems.is_empty() && tuple.elems.iter().all(is_arbitrary),
+        _ => false,
+    }
+}
@@ -39,6 +39,26 @@
 //!                                 "anchor_lang :: solana_program :: pubkey :: Pubkey",
 //!                             ),
 //!                         ],
+//!                         account_metas: [
+//!                             IdlAccountMeta {
+//!                                 name: "state",
+//!                                 checked: true,
+//!                                 kind: Created,
+//!                             },
+//!                             IdlAccountMeta {
+//!                                 name: "user",
+//!                                 checked: false,
+//!                                 kind: Signer,
+//!                             },
+//!                             IdlAccountMeta {
+//!                                 name: "system_program",
+//!                                 checked: true,
+//!                                 kind: Program(
+//!                                     "System",
+//!                                 ),
+//!                             },
+//!                         ],
+//!                         nested_groups: [],
 //!                     },
 //!                 ),
 //!                 (
@@ -65,6 +85,14 @@
 //!                                 "anchor_lang :: solana_program :: pubkey :: Pubkey",
 //!                             ),
 //!                         ],
+//!                         account_metas: [
+//!                             IdlAccountMeta {
+//!                                 name: "state",
+//!                                 checked: true,
+//!                                 kind: Other,
+//!                             },
+//!                         ],
+//!                         nested_groups: [],
 //!                     },
 //!                 ),
 //!                 (
@@ -86,6 +114,14 @@
 //!                                 "anchor_lang :: solana_program :: pubkey :: Pubkey",
 //!                             ),
 //!                         ],
+//!                         account_metas: [
+//!                             IdlAccountMeta {
+//!                                 name: "state",
+//!                                 checked: true,
+//!                                 kind: Other,
+//!                             },
+//!                         ],
+//!                         nested_groups: [],
 //!                     },
 //!                 ),
 //!             ],
@@ -94,11 +130,20 @@
 //! }
 //! ```
 
+use std::collections::HashMap;
+
 use heck::{ToSnakeCase, ToUpperCamelCase};
 use quote::ToTokens;
 use thiserror::Error;
 
 static ACCOUNT_MOD_PREFIX: &str = "__client_accounts_";
+static CPI_ACCOUNT_MOD_PREFIX: &str = "__cpi_client_accounts_";
+
+/// Account types which are checked neither by their owner nor by their address.
+static UNCHECKED_ACCOUNT_TYPES: &[&str] = &["Signer", "UncheckedAccount", "AccountInfo"];
+
+/// Constraints of the `#[account(..)]` attribute checking the owner or the address of the account.
+static CHECKING_CONSTRAINTS: &[&str] = &["owner", "address", "seeds"];
 
 #[derive(Error, Debug)]
 pub enum Error {
@@ -136,6 +181,41 @@ pub struct IdlInstruction {
 pub struct IdlAccountGroup {
     pub name: IdlName,
     pub accounts: Vec<(String, String)>,
+    /// Accounts in the order of `to_account_metas`, the nested account groups are flattened.
+    pub account_metas: Vec<IdlAccountMeta>,
+    /// Names of the nested account groups prefixed like the [account_metas](Self::account_metas)
+    /// with the names of their account structs, e.g. `("inner", "Inner")`.
+    pub nested_groups: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IdlAccountMeta {
+    /// Name of the account, the accounts of a nested group are prefixed
+    /// by the name of the group, e.g. `inner.state`.
+    pub name: String,
+    /// `false` for accounts without any owner, type or address check,
+    /// e.g. a plain `Signer` or an `UncheckedAccount`.
+    pub checked: bool,
+    /// Kind of the account derived from its type and constraints.
+    pub kind: IdlAccountKind,
+}
+
+/// The kind of the account determining the value the account can be filled with,
+/// e.g. in the generated fuzz tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdlAccountKind {
+    /// A `Signer` account or an account with the `signer` constraint, e.g. the payer of the instruction.
+    Signer,
+    /// A `Program` account with the name of the program type, e.g. `System`.
+    Program(String),
+    /// An account with the `address` constraint with the address expression, e.g. `my_program::ID`.
+    Address(String),
+    /// An account with the `seeds` constraint, i.e. a PDA.
+    Pda,
+    /// An account created by the instruction (`init` without `seeds`), its keypair has to sign.
+    Created,
+    /// Any other account, e.g. an existing account of the program.
+    Other,
 }
 
 pub async fn parse_to_idl_program(name: String, code: &str) -> Result<IdlProgram, Error> {
@@ -143,6 +223,7 @@ pub async fn parse_to_idl_program(name: String, code: &str) -> Result<IdlProgram
     let mut mod_private = None::<syn::ItemMod>;
     let mut mod_instruction = None::<syn::ItemMod>;
     let mut account_mods = Vec::<syn::ItemMod>::new();
+    let mut account_structs = HashMap::<String, syn::ItemStruct>::new();
 
     for item in syn::parse_file(code)?.items.into_iter() {
         match item {
@@ -152,8 +233,9 @@ pub async fn parse_to_idl_program(name: String, code: &str) -> Result<IdlProgram
             syn::Item::Mod(item_mod) => match item_mod.ident.to_string().as_str() {
                 "__private" => mod_private = Some(item_mod),
                 "instruction" => mod_instruction = Some(item_mod),
-                _ => set_account_modules(&mut account_mods, item_mod),
+                _ => set_account_modules(&mut account_mods, &mut account_structs, item_mod),
             },
+            syn::Item::Struct(item_struct) => set_account_struct(&mut account_structs, item_struct),
             _ => (),
         }
     }
@@ -321,6 +403,8 @@ pub async fn parse_to_idl_program(name: String, code: &str) -> Result<IdlProgram
                     upper_camel_case: account_group_name,
                 },
                 accounts: Vec::new(),
+                account_metas: Vec::new(),
+                nested_groups: Vec::new(),
             };
             Some((idl_instruction, idl_account))
         })
@@ -405,6 +489,7 @@ pub async fn parse_to_idl_program(name: String, code: &str) -> Result<IdlProgram
     //     }
     // ```
 
+    let mut client_accounts = HashMap::<String, Vec<(String, String)>>::new();
     for account_mod_item in account_mods {
         let account_struct_name = account_mod_item
             .ident
@@ -454,6 +539,21 @@ pub async fn parse_to_idl_program(name: String, code: &str) -> Result<IdlProgram
                 idl_account_group.accounts = accounts.clone();
             }
         }
+        client_accounts.insert(account_struct_name, accounts);
+    }
+
+    for (_, idl_account_group) in &mut instruction_account_pairs {
+        idl_account_group.account_metas = flatten_account_metas(
+            &idl_account_group.name.upper_camel_case,
+            "",
+            &client_accounts,
+            &account_structs,
+        );
+        idl_account_group.nested_groups = nested_account_groups(
+            &idl_account_group.name.upper_camel_case,
+            "",
+            &client_accounts,
+        );
     }
 
     // ------ // ------
@@ -468,7 +568,186 @@ pub async fn parse_to_idl_program(name: String, code: &str) -> Result<IdlProgram
     })
 }
 
-fn set_account_modules(account_modules: &mut Vec<syn::ItemMod>, item_module: syn::ItemMod) {
+/// Lists the accounts of the group in the order of `to_account_metas`, which pushes
+/// the accounts of a nested group in place of the group.
+///
+/// Nested groups are fields of the `__client_accounts_*` structs
+/// with the type `__client_accounts_<group>::<Group>`.
+fn flatten_account_metas(
+    account_group_name: &str,
+    name_prefix: &str,
+    client_accounts: &HashMap<String, Vec<(String, String)>>,
+    account_structs: &HashMap<String, syn::ItemStruct>,
+) -> Vec<IdlAccountMeta> {
+    let accounts = match client_accounts.get(account_group_name) {
+        Some(accounts) => accounts,
+        None => return Vec::new(),
+    };
+    accounts
+        .iter()
+        .flat_map(|(account_name, account_id_type)| {
+            let name = format!("{name_prefix}{account_name}");
+            if account_id_type.contains(ACCOUNT_MOD_PREFIX) {
+                let nested_group_name = account_id_type.rsplit("::").next().unwrap().trim();
+                return flatten_account_metas(
+                    nested_group_name,
+                    &format!("{name}."),
+                    client_accounts,
+                    account_structs,
+                );
+            }
+            let field = account_structs
+                .get(account_group_name)
+                .and_then(|account_struct| {
+                    account_struct
+                        .fields
+                        .iter()
+                        .find(|field| matches!(&field.ident, Some(ident) if ident == account_name))
+                });
+            vec![IdlAccountMeta {
+                name,
+                // Accounts whose declaration cannot be found are considered checked
+                checked: field.map_or(true, is_account_checked),
+                kind: field.map_or(IdlAccountKind::Other, account_kind),
+            }]
+        })
+        .collect()
+}
+
+/// Lists the nested account groups of the group with the names of their account structs,
+/// the names are prefixed by the names of the parent groups like in [flatten_account_metas].
+fn nested_account_groups(
+    account_group_name: &str,
+    name_prefix: &str,
+    client_accounts: &HashMap<String, Vec<(String, String)>>,
+) -> Vec<(String, String)> {
+    let accounts = match client_accounts.get(account_group_name) {
+        Some(accounts) => accounts,
+        None => return Vec::new(),
+    };
+    accounts
+        .iter()
+        .filter(|(_, account_id_type)| account_id_type.contains(ACCOUNT_MOD_PREFIX))
+        .flat_map(|(account_name, account_id_type)| {
+            let name = format!("{name_prefix}{account_name}");
+            let nested_group_name = account_id_type.rsplit("::").next().unwrap().trim();
+            let mut nested_groups =
+                nested_account_groups(nested_group_name, &format!("{name}."), client_accounts);
+            nested_groups.insert(0, (name, nested_group_name.to_owned()));
+            nested_groups
+        })
+        .collect()
+}
+
+/// Whether the owner, type or address of the account is checked by its type
+/// or by a constraint of its `#[account(..)]` attribute.
+fn is_account_checked(field: &syn::Field) -> bool {
+    let checked_type = account_type_name(&field.ty).map_or(true, |type_name| {
+        !UNCHECKED_ACCOUNT_TYPES.contains(&type_name.as_str())
+    });
+    let checked_constraint = CHECKING_CONSTRAINTS
+        .iter()
+        .any(|name| has_constraint(field, name));
+    checked_type || checked_constraint
+}
+
+fn account_kind(field: &syn::Field) -> IdlAccountKind {
+    match account_type_name(&field.ty).as_deref() {
+        Some("Signer") => IdlAccountKind::Signer,
+        Some("Program") => {
+            program_type_name(&field.ty).map_or(IdlAccountKind::Other, IdlAccountKind::Program)
+        }
+        _ if has_constraint(field, "signer") => IdlAccountKind::Signer,
+        _ => match constraint_value(field, "address") {
+            Some(address) if address.ends_with("system_program::ID") => {
+                IdlAccountKind::Program("System".to_owned())
+            }
+            Some(address) => IdlAccountKind::Address(address),
+            None if has_constraint(field, "seeds") => IdlAccountKind::Pda,
+            None if has_constraint(field, "init") || has_constraint(field, "init_if_needed") => {
+                IdlAccountKind::Created
+            }
+            None => IdlAccountKind::Other,
+        },
+    }
+}
+
+/// Whether the `#[account(..)]` attribute of the account contains the constraint,
+/// e.g. `init` or `seeds = [..]`.
+fn has_constraint(field: &syn::Field, name: &str) -> bool {
+    constraint_value(field, name).is_some()
+}
+
+/// Value of the constraint in the `#[account(..)]` attribute of the account without spaces,
+/// e.g. `system_program::ID` for `address = system_program::ID`, or an empty string
+/// for the constraints without a value like `init`.
+fn constraint_value(field: &syn::Field, name: &str) -> Option<String> {
+    field
+        .attrs
+        .iter()
+        .filter(|attr| attr.path.is_ident("account"))
+        .find_map(|attr| {
+            // tokens example: `(init , payer = user , space = 8 + 2)`
+            let tokens = attr.tokens.to_string().replace(' ', "");
+            tokens
+                .trim_start_matches('(')
+                .trim_end_matches(')')
+                .split(',')
+                .find_map(|constraint| {
+                    let rest = constraint.strip_prefix(name)?;
+                    if rest.is_empty() {
+                        Some(String::new())
+                    } else if rest.starts_with('=') && !rest.starts_with("==") {
+                        Some(rest[1..].to_owned())
+                    } else {
+                        None
+                    }
+                })
+        })
+}
+
+/// Name of the program type of a `Program` account, e.g. `System`.
+fn program_type_name(ty: &syn::Type) -> Option<String> {
+    let segment = match ty {
+        syn::Type::Path(type_path) => type_path.path.segments.last()?,
+        _ => None?,
+    };
+    match &segment.arguments {
+        syn::PathArguments::AngleBracketed(arguments) => {
+            arguments.args.iter().find_map(|argument| match argument {
+                syn::GenericArgument::Type(syn::Type::Path(type_path)) => type_path
+                    .path
+                    .segments
+                    .last()
+                    .map(|segment| segment.ident.to_string()),
+                _ => None,
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Name of the account type without the `Box` and `Option` wrappers, e.g. `Account`.
+fn account_type_name(ty: &syn::Type) -> Option<String> {
+    let segment = match ty {
+        syn::Type::Path(type_path) => type_path.path.segments.last()?,
+        _ => None?,
+    };
+    if segment.ident == "Box" || segment.ident == "Option" {
+        if let syn::PathArguments::AngleBracketed(arguments) = &segment.arguments {
+            if let Some(syn::GenericArgument::Type(inner)) = arguments.args.first() {
+                return account_type_name(inner);
+            }
+        }
+    }
+    Some(segment.ident.to_string())
+}
+
+fn set_account_modules(
+    account_modules: &mut Vec<syn::ItemMod>,
+    account_structs: &mut HashMap<String, syn::ItemStruct>,
+    item_module: syn::ItemMod,
+) {
     if item_module
         .ident
         .to_string()
@@ -477,6 +756,14 @@ fn set_account_modules(account_modules: &mut Vec<syn::ItemMod>, item_module: syn
         account_modules.push(item_module);
         return;
     }
+    // The CPI structs mirror the account structs with `AccountInfo` fields
+    if item_module
+        .ident
+        .to_string()
+        .starts_with(CPI_ACCOUNT_MOD_PREFIX)
+    {
+        return;
+    }
     let modules = item_module
         .content
         .ok_or(Error::MissingOrInvalidProgramItems(
@@ -484,9 +771,106 @@ fn set_account_modules(account_modules: &mut Vec<syn::ItemMod>, item_module: syn
         ))
         .unwrap()
         .1;
-    for module in modules {
-        if let syn::Item::Mod(nested_module) = module {
-            set_account_modules(account_modules, nested_module);
+    for item in modules {
+        match item {
+            syn::Item::Mod(nested_module) => {
+                set_account_modules(account_modules, account_structs, nested_module)
+            }
+            syn::Item::Struct(item_struct) => set_account_struct(account_structs, item_struct),
+            _ => (),
         }
     }
 }
+
+/// Stores the struct when it may derive `Accounts`, i.e. it has the `'info` lifetime.
+fn set_account_struct(
+    account_structs: &mut HashMap<String, syn::ItemStruct>,
+    item_struct: syn::ItemStruct,
+) {
+    if item_struct.generics.lifetimes().next().is_some() {
+        account_structs.insert(item_struct.ident.to_string(), item_struct);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    #[test]
+    fn test_flatten_account_metas() {
+        let pubkey = "anchor_lang :: solana_program :: pubkey :: Pubkey";
+        let client_accounts = HashMap::from([
+            (
+                "Outer".to_owned(),
+                vec![
+                    ("authority".to_owned(), pubkey.to_owned()),
+                    (
+                        "inner".to_owned(),
+                        "__client_accounts_inner :: Inner".to_owned(),
+                    ),
+                    ("system_program".to_owned(), pubkey.to_owned()),
+                    ("oracle".to_owned(), pubkey.to_owned()),
+                ],
+            ),
+            (
+                "Inner".to_owned(),
+                vec![
+                    ("state".to_owned(), pubkey.to_owned()),
+                    ("payer".to_owned(), pubkey.to_owned()),
+                ],
+            ),
+        ]);
+        let mut account_structs = HashMap::new();
+        set_account_struct(
+            &mut account_structs,
+            parse_quote! {
+                pub struct Outer<'info> {
+                    pub authority: Signer<'info>,
+                    pub inner: Inner<'info>,
+                    pub system_program: Program<'info, System>,
+                    # [account (address = oracle :: ID)]
+                    pub oracle: AccountInfo<'info>,
+                }
+            },
+        );
+        set_account_struct(
+            &mut account_structs,
+            parse_quote! {
+                pub struct Inner<'info> {
+                    # [account (mut , seeds = [b"state" , authority . key () . as_ref ()] , bump)]
+                    pub state: UncheckedAccount<'info>,
+                    # [account (mut , constraint = payer . owner == authority . key ())]
+                    pub payer: Option<AccountInfo<'info>>,
+                }
+            },
+        );
+
+        let account_metas = flatten_account_metas("Outer", "", &client_accounts, &account_structs)
+            .into_iter()
+            .map(|account| (account.name, account.checked, account.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            account_metas,
+            [
+                ("authority".to_owned(), false, IdlAccountKind::Signer),
+                ("inner.state".to_owned(), true, IdlAccountKind::Pda),
+                ("inner.payer".to_owned(), false, IdlAccountKind::Other),
+                (
+                    "system_program".to_owned(),
+                    true,
+                    IdlAccountKind::Program("System".to_owned())
+                ),
+                (
+                    "oracle".to_owned(),
+                    true,
+                    IdlAccountKind::Address("oracle::ID".to_owned())
+                ),
+            ]
+        );
+        assert_eq!(
+            nested_account_groups("Outer", "", &client_accounts),
+            [("inner".to_owned(), "Inner".to_owned())]
+        );
+    }
+}
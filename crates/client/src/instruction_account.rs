@@ -0,0 +1,12 @@
+/// Account of an instruction generated in the `program_client` crate,
+/// e.g. `turnstile_instruction::PUSH_ACCOUNTS` lists the accounts of the `push` instruction
+/// in the order of `to_account_metas`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InstructionAccount {
+    /// Name of the account, the accounts of a nested account group are prefixed
+    /// by the name of the group, e.g. `inner.state`.
+    pub name: &'static str,
+    /// `false` for accounts without any owner, type or address check,
+    /// e.g. a plain `Signer` or an `UncheckedAccount`.
+    pub checked: bool,
+}
@@ -25,9 +25,24 @@ pub use trdelnik_test::{trdelnik_fuzz, trdelnik_test};
 
 mod config;
 
+mod anchor_error;
+pub use anchor_error::{assert_anchor_error, AnchorErrorInfo, AnchorErrorOrigin};
+
 mod client;
 pub use client::Client;
+pub use client::ComputeUnits;
 pub use client::PrintableTransaction;
+pub use client::SimulatedTransaction;
+pub use client::ValidatorSnapshot;
+
+mod instruction_account;
+pub use instruction_account::InstructionAccount;
+
+mod events;
+pub use events::{decode_events, EventCollector, TransactionEvents};
+
+mod migration;
+pub use migration::{Error as MigrationError, ProgramMigration};
 
 mod reader;
 pub use reader::Reader;
@@ -38,12 +53,16 @@ pub use commander::{Commander, RunTestOptions};
 mod tester;
 pub use tester::Tester;
 
+mod shared;
+pub use shared::{shared_validator, shared_validator_with, SharedValidatorContext};
+
 mod temp_clone;
 pub use temp_clone::TempClone;
 
 mod keys;
 pub use keys::*;
 
+pub mod fuzz_test_generator;
 pub mod idl;
 pub mod program_client_generator;
 
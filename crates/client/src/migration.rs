@@ -0,0 +1,137 @@
+use crate::{reader, Client, Reader};
+use anchor_client::{
+    solana_sdk::{
+        pubkey::Pubkey,
+        signer::{keypair::Keypair, Signer},
+    },
+    ClientError,
+};
+use fehler::throws;
+use log::debug;
+use std::{future::Future, io, path::PathBuf};
+use thiserror::Error;
+use tokio::fs;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("cannot read the previous version of the program")]
+    Io(#[from] io::Error),
+    #[error("cannot read the new version of the program")]
+    Reader(#[from] reader::Error),
+    #[error("{0}")]
+    Client(#[from] ClientError),
+}
+
+/// `ProgramMigration` tests that the accounts created by the previous version of the program
+/// still work after the program is upgraded in place to the freshly built one.
+///
+/// The previous version is read from a `.so` file, e.g. from a directory with the artifacts
+/// of a git tag, and deployed through the upgradeable loader. The new version is read
+/// from `target/deploy` by [Reader].
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let migration = ProgramMigration::new(
+///     program_keypair(0),
+///     "turnstile",
+///     "../artifacts/v1.0.0/turnstile.so",
+/// );
+/// let state = migration
+///     .run(&client, |client| async move {
+///         let state = Keypair::new();
+///         turnstile_instruction::initialize(client, /* ... */).await?;
+///         Ok::<_, anyhow::Error>(state)
+///     })
+///     .await?;
+/// // The program is upgraded, continue with the accounts created by the previous version
+/// turnstile_instruction::coin(&client, /* ... */).await?;
+/// ```
+pub struct ProgramMigration {
+    program_keypair: Keypair,
+    upgrade_authority: Keypair,
+    program_name: String,
+    previous_program_path: PathBuf,
+    reader: Reader,
+}
+
+impl ProgramMigration {
+    pub fn new(
+        program_keypair: Keypair,
+        program_name: &str,
+        previous_program_path: impl Into<PathBuf>,
+    ) -> Self {
+        Self {
+            program_keypair,
+            upgrade_authority: Keypair::new(),
+            program_name: program_name.to_owned(),
+            previous_program_path: previous_program_path.into(),
+            reader: Reader::new(),
+        }
+    }
+
+    /// Sets the upgrade authority of the program, a new keypair by default.
+    pub fn with_upgrade_authority(mut self, upgrade_authority: Keypair) -> Self {
+        self.upgrade_authority = upgrade_authority;
+        self
+    }
+
+    /// Sets the reader of the new version of the program, [Reader::new] by default.
+    pub fn with_reader(mut self, reader: Reader) -> Self {
+        self.reader = reader;
+        self
+    }
+
+    pub fn program_id(&self) -> Pubkey {
+        self.program_keypair.pubkey()
+    }
+
+    pub fn upgrade_authority(&self) -> &Keypair {
+        &self.upgrade_authority
+    }
+
+    /// Deploys the previous version of the program,
+    /// its program data account is big enough for both the versions.
+    #[throws]
+    pub async fn deploy_previous(&self, client: &Client) {
+        let previous_program_data = fs::read(&self.previous_program_path).await?;
+        let program_data = self.reader.program_data(&self.program_name).await?;
+        debug!(
+            "deploying the previous version of {} from {}",
+            self.program_name,
+            self.previous_program_path.display()
+        );
+        client
+            .deploy_upgradeable(
+                &self.program_keypair,
+                &self.upgrade_authority,
+                &previous_program_data,
+                Some((previous_program_data.len() * 2).max(program_data.len())),
+            )
+            .await?;
+    }
+
+    /// Upgrades the program to the new version.
+    #[throws]
+    pub async fn upgrade(&self, client: &Client) {
+        let program_data = self.reader.program_data(&self.program_name).await?;
+        debug!("upgrading {} to the new version", self.program_name);
+        client
+            .upgrade_program(self.program_id(), &self.upgrade_authority, &program_data)
+            .await?;
+    }
+
+    /// Deploys the previous version of the program, runs the setup against it, upgrades
+    /// the program and returns the output of the setup, e.g. the keypairs of the created accounts.
+    pub async fn run<'a, T, E, F, Fut>(&self, client: &'a Client, setup: F) -> Result<T, E>
+    where
+        F: FnOnce(&'a Client) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: From<Error>,
+    {
+        self.deploy_previous(client).await?;
+        let output = setup(client).await?;
+        self.upgrade(client).await?;
+        Ok(output)
+    }
+}
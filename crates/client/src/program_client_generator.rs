@@ -1,5 +1,5 @@
 use crate::idl::Idl;
-use quote::{format_ident, ToTokens};
+use quote::{format_ident, quote, ToTokens};
 use syn::{parse_quote, parse_str};
 
 /// Generates `program_client`'s `lib.rs` from [Idl] created from Anchor programs.
@@ -30,7 +30,15 @@ pub fn generate_source_code(idl: Idl, use_modules: &[syn::ItemUse]) -> String {
                         let account_struct_name: syn::Ident =
                             parse_str(&idl_account_group.name.upper_camel_case).unwrap();
                         let instruction_name: syn::Ident =
-                            parse_str(&(idl_instruction.name.snake_case + "_ix")).unwrap();
+                            parse_str(&(idl_instruction.name.snake_case.clone() + "_ix")).unwrap();
+                        let accounts_const_name = format_ident!(
+                            "{}_ACCOUNTS",
+                            idl_instruction.name.snake_case.to_uppercase()
+                        );
+                        let accounts = idl_account_group.account_metas.iter().map(|account| {
+                            let (name, checked) = (&account.name, account.checked);
+                            quote! { InstructionAccount { name: #name, checked: #checked } }
+                        });
 
                         let instruction: syn::ItemFn = parse_quote! {
                             pub async fn #instruction_fn_name(
@@ -61,8 +69,15 @@ pub fn generate_source_code(idl: Idl, use_modules: &[syn::ItemUse]) -> String {
                             }
                         };
 
-                        instructions.push(instruction);
-                        instructions.push(instruction_raw);
+                        // Accounts in the order of `to_account_metas`,
+                        // used e.g. by the account substitution fuzzing
+                        let accounts_const: syn::Item = parse_quote! {
+                            pub const #accounts_const_name: &[InstructionAccount] = &[#(#accounts),*];
+                        };
+
+                        instructions.push(syn::Item::Fn(instruction));
+                        instructions.push(syn::Item::Fn(instruction_raw));
+                        instructions.push(accounts_const);
                         instructions
                     },
                 )
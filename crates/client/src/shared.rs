@@ -0,0 +1,147 @@
+use crate::{Client, Validator};
+use anchor_client::solana_sdk::{
+    native_token::sol_to_lamports,
+    pubkey::Pubkey,
+    signer::{keypair::Keypair, Signer},
+};
+use log::{debug, error};
+use rstest::fixture;
+use std::{panic, thread};
+use tokio::{
+    runtime::{self, Runtime},
+    sync::{oneshot, Mutex},
+};
+
+lazy_static::lazy_static! {
+    static ref SHARED_VALIDATOR: Mutex<Option<SharedValidator>> = Mutex::new(None);
+}
+
+/// The shared validator with the runtime it was started on, so it does not depend
+/// on the runtime of the test which started it. They keep running until the test binary exits,
+/// then they are dropped by [drop_shared_validator].
+struct SharedValidator {
+    client: Client,
+    runtime: Runtime,
+    /// The initializer the validator was created by, the other ones are rejected.
+    initialize_validator: fn() -> Validator,
+}
+
+/// The lamports airdropped to the payer of every test.
+const PAYER_LAMPORTS: f64 = 100.;
+
+/// One test running on the validator shared by all the tests of the test binary.
+pub struct SharedValidatorContext {
+    /// Client with a new payer funded for the test.
+    pub client: Client,
+    /// Unique for every test, use it as a seed of the program derived addresses
+    /// so the accounts of the tests running in parallel do not collide.
+    pub namespace: Pubkey,
+}
+
+/// The [rstest] fixture of a test running on the validator shared by all the tests
+/// of the test binary, the validator is created by [Validator::default].
+///
+/// The validator is started by the first test and runs until the test binary exits,
+/// so the tests do not pay the startup cost and they do not have to run serially.
+/// The validator is shut down and its ledger is removed when the test binary exits.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[trdelnik_test]
+/// async fn test_happy_path(#[future] shared_validator: SharedValidatorContext) {
+///     let SharedValidatorContext { client, namespace } = shared_validator.await;
+///     // ...
+/// }
+/// ```
+#[fixture]
+pub async fn shared_validator() -> SharedValidatorContext {
+    shared_validator_with(Validator::default).await
+}
+
+/// Returns the context of a test running on the shared validator,
+/// see [shared_validator](fn@shared_validator).
+///
+/// The validator is created by `initialize_validator` in the first call, e.g. to add the programs.
+/// All the tests of the test binary have to pass the same function, because there is only
+/// one validator.
+///
+/// # Panics
+///
+/// Panics when the validator has been already started by a different `initialize_validator`.
+pub async fn shared_validator_with(
+    initialize_validator: fn() -> Validator,
+) -> SharedValidatorContext {
+    let client = {
+        let mut shared_validator = SHARED_VALIDATOR.lock().await;
+        if shared_validator.is_none() {
+            debug!("starting the shared validator");
+            *shared_validator = Some(start_shared_validator(initialize_validator).await);
+            // The validator is started only once, so the hook is registered only once
+            unsafe {
+                libc::atexit(drop_shared_validator);
+            }
+        }
+        let shared_validator = shared_validator.as_ref().unwrap();
+        assert!(
+            shared_validator.initialize_validator == initialize_validator,
+            "Error: The shared validator has been already started by a different initializer, \
+            all the tests have to use the same one"
+        );
+        shared_validator.client.clone_with_payer(Keypair::new())
+    };
+    client
+        .airdrop(client.payer().pubkey(), sol_to_lamports(PAYER_LAMPORTS))
+        .await
+        .expect("funding the payer of the test failed");
+
+    SharedValidatorContext {
+        client,
+        namespace: Keypair::new().pubkey(),
+    }
+}
+
+/// Starts the validator on a new runtime running on a dedicated thread.
+async fn start_shared_validator(initialize_validator: fn() -> Validator) -> SharedValidator {
+    let mut validator = initialize_validator();
+    let (sender, receiver) = oneshot::channel();
+    thread::spawn(move || {
+        let runtime = runtime::Builder::new_multi_thread()
+            .enable_all()
+            .build()
+            .expect("creating the runtime of the shared validator failed");
+        let client = runtime.block_on(validator.start());
+        let _ = sender.send(SharedValidator {
+            client,
+            runtime,
+            initialize_validator,
+        });
+    });
+    receiver
+        .await
+        .expect("starting the shared validator failed")
+}
+
+/// Shuts down the shared validator when the test binary exits, which removes its ledger.
+///
+/// It's skipped when a test is still starting the validator, the lock is held then.
+extern "C" fn drop_shared_validator() {
+    let shared_validator = match SHARED_VALIDATOR.try_lock() {
+        Ok(mut shared_validator) => shared_validator.take(),
+        Err(_) => return,
+    };
+    if let Some(SharedValidator {
+        client, runtime, ..
+    }) = shared_validator
+    {
+        debug!("shutting down the shared validator");
+        // Panics cannot unwind out of the `extern "C"` hook
+        let result = panic::catch_unwind(panic::AssertUnwindSafe(move || {
+            drop(client);
+            runtime.shutdown_background();
+        }));
+        if result.is_err() {
+            error!("Error shutting down the shared validator");
+        }
+    }
+}
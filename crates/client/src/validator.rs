@@ -1,7 +1,8 @@
 use std::{
+    collections::HashSet,
     fs,
     net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener},
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{Arc, RwLock},
     time::{SystemTime, UNIX_EPOCH},
 };
@@ -14,19 +15,34 @@ use solana_core::tower_storage::NullTowerStorage;
 use solana_faucet::faucet::{self, run_local_faucet_with_port};
 use solana_rpc::rpc::JsonRpcConfig;
 use solana_sdk::{
-    account::AccountSharedData, native_token::sol_to_lamports, pubkey::Pubkey, signature::Keypair,
-    signer::Signer, system_program,
+    account::{AccountSharedData, WritableAccount},
+    bpf_loader_upgradeable::{self, UpgradeableLoaderState},
+    clock::{Slot, UnixTimestamp},
+    epoch_schedule::EpochSchedule,
+    feature::{self, Feature},
+    fee_calculator::FeeRateGovernor,
+    native_token::sol_to_lamports,
+    pubkey::Pubkey,
+    rent::Rent,
+    signature::Keypair,
+    signer::Signer,
+    system_program,
 };
 use solana_validator::{admin_rpc_service, redirect_stderr_to_file, test_validator::*};
 use symlink::symlink_file;
 
-use crate::{Client, TempClone};
+use crate::{
+    config::{Config, CONFIG},
+    Client, TempClone,
+};
 
 const N_TRIES_FIND_RPC_PORT: u8 = 10;
 
 pub struct Validator {
     genesis_validator: TestValidatorGenesis,
     ledger_path: PathBuf,
+    unix_timestamp: Option<UnixTimestamp>,
+    deactivated_features: HashSet<Pubkey>,
 }
 
 fn request_local_address_rpc() -> (SocketAddr, SocketAddr) {
@@ -77,6 +93,43 @@ fn generate_temp_dir() -> PathBuf {
 }
 
 impl Validator {
+    /// Applies the runtime settings from the `validator` section of `Trdelnik.toml`.
+    fn configure_runtime(&mut self) {
+        let config = &CONFIG.validator;
+        self.deactivate_features(&config.deactivate_features);
+        self.activate_features(&config.activate_features);
+        if let Some(compute_unit_limit) = config.compute_unit_limit {
+            self.compute_unit_limit(compute_unit_limit);
+        }
+        if let Some(ticks_per_slot) = config.ticks_per_slot {
+            self.ticks_per_slot(ticks_per_slot);
+        }
+        if let Some(slots_per_epoch) = config.slots_per_epoch {
+            self.slots_per_epoch(slots_per_epoch);
+        }
+        if let Some(rent) = config.rent {
+            self.rent(rent);
+        }
+        if let Some(fee_rate_governor) = &config.fee_rate_governor {
+            self.fee_rate_governor(fee_rate_governor.clone());
+        }
+    }
+
+    /// Adds the accounts and the programs listed in the `fixtures` section of `Trdelnik.toml`.
+    fn add_fixtures(&mut self) {
+        let fixtures = &CONFIG.fixtures;
+        if fixtures.accounts.is_empty() && fixtures.programs.is_empty() {
+            return;
+        }
+        let root = Config::discover_root().expect("failed to find the root folder");
+        for account_path in fixtures.accounts.iter() {
+            self.add_account_from_file(root.join(account_path));
+        }
+        for program in fixtures.programs.iter() {
+            self.add_program_from_file(program.program_id, root.join(&program.path));
+        }
+    }
+
     fn start_admin_rcp(&mut self, rpc_addr: SocketAddr) {
         let genesis = &self.genesis_validator;
         let admin_service_post_init = Arc::new(RwLock::new(None));
@@ -166,7 +219,16 @@ impl Validator {
     }
 
     pub fn add_program(&mut self, program_name: &str, program_id: Pubkey) -> &mut Self {
-        let program_path = PathBuf::from(format!("../target/deploy/{program_name}.so"));
+        self.add_program_from_file(program_id, format!("../target/deploy/{program_name}.so"))
+    }
+
+    /// Adds the program from the `.so` file, e.g. dumped by `solana program dump <ADDRESS> <FILE>`.
+    pub fn add_program_from_file(
+        &mut self,
+        program_id: Pubkey,
+        program_path: impl AsRef<Path>,
+    ) -> &mut Self {
+        let program_path = program_path.as_ref().to_path_buf();
         if !program_path.exists() {
             panic!(
                 "Error: Unable to find program at path: {}",
@@ -183,6 +245,90 @@ impl Validator {
         self
     }
 
+    /// Adds the program deployed through the upgradeable loader (`bpf_loader_upgradeable`)
+    /// with the upgrade authority, so upgrades and authority checks can be tested.
+    pub fn add_upgradeable_program(
+        &mut self,
+        program_name: &str,
+        program_id: Pubkey,
+        upgrade_authority: Pubkey,
+    ) -> &mut Self {
+        self.add_upgradeable_program_from_file(
+            program_id,
+            upgrade_authority,
+            format!("../target/deploy/{program_name}.so"),
+        )
+    }
+
+    /// Adds the program from the `.so` file deployed through the upgradeable loader,
+    /// see [Validator::add_upgradeable_program].
+    pub fn add_upgradeable_program_from_file(
+        &mut self,
+        program_id: Pubkey,
+        upgrade_authority: Pubkey,
+        program_path: impl AsRef<Path>,
+    ) -> &mut Self {
+        let program_path = program_path.as_ref();
+        let program_data = fs::read(program_path).unwrap_or_else(|err| {
+            panic!(
+                "Error: Unable to read program at path {}: {}",
+                program_path.display(),
+                err
+            )
+        });
+
+        let (programdata_address, _) =
+            Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id());
+        let mut program_account = AccountSharedData::new_data(
+            self.genesis_validator
+                .rent
+                .minimum_balance(UpgradeableLoaderState::size_of_program()),
+            &UpgradeableLoaderState::Program {
+                programdata_address,
+            },
+            &bpf_loader_upgradeable::id(),
+        )
+        .unwrap();
+        program_account.set_executable(true);
+
+        let mut data = bincode::serialize(&UpgradeableLoaderState::ProgramData {
+            slot: 0,
+            upgrade_authority_address: Some(upgrade_authority),
+        })
+        .unwrap();
+        data.resize(UpgradeableLoaderState::size_of_programdata_metadata(), 0);
+        data.extend_from_slice(&program_data);
+        let mut programdata_account = AccountSharedData::new(
+            self.genesis_validator.rent.minimum_balance(data.len()),
+            0,
+            &bpf_loader_upgradeable::id(),
+        );
+        programdata_account.set_data(data);
+
+        self.add_account(program_id, program_account)
+            .add_account(programdata_address, programdata_account)
+    }
+
+    /// Adds the account dumped by `solana account --output json <ADDRESS>`,
+    /// the account is stored at the address from the file.
+    pub fn add_account_from_file(&mut self, account_path: impl AsRef<Path>) -> &mut Self {
+        let account_path = account_path.as_ref();
+        if !account_path.exists() {
+            panic!(
+                "Error: Unable to find account at path: {}",
+                account_path.display()
+            );
+        }
+
+        self.genesis_validator
+            .add_accounts_from_json_files(&[AccountInfo {
+                address: None,
+                filename: account_path.to_str().expect("Invalid account path"),
+            }])
+            .unwrap_or_else(|err| panic!("Error: {err}"));
+        self
+    }
+
     pub fn add_account(&mut self, address: Pubkey, account: AccountSharedData) -> &mut Self {
         self.genesis_validator.add_account(address, account);
         self
@@ -193,17 +339,100 @@ impl Validator {
         self
     }
 
+    /// Starts the validator at the slot instead of the slot 0.
+    pub fn warp_slot(&mut self, slot: Slot) -> &mut Self {
+        self.genesis_validator.warp_slot(slot);
+        self
+    }
+
+    /// Sets the `unix_timestamp` of the clock when the validator is started,
+    /// see [Client::set_unix_timestamp].
+    ///
+    /// The validator starts at the current time and never moves its clock backwards,
+    /// so a timestamp in the past is ignored.
+    pub fn unix_timestamp(&mut self, unix_timestamp: UnixTimestamp) -> &mut Self {
+        self.unix_timestamp = Some(unix_timestamp);
+        self
+    }
+
+    /// Deactivates the runtime features in the genesis, all the other features are active.
+    pub fn deactivate_features(&mut self, features: &[Pubkey]) -> &mut Self {
+        self.deactivated_features.extend(features);
+        self
+    }
+
+    /// Activates the runtime features in the genesis, e.g. the features deactivated
+    /// in `Trdelnik.toml` or features unknown to the test validator.
+    pub fn activate_features(&mut self, features: &[Pubkey]) -> &mut Self {
+        let lamports = self
+            .genesis_validator
+            .rent
+            .minimum_balance(Feature::size_of());
+        for feature_id in features {
+            self.deactivated_features.remove(feature_id);
+            let account = feature::create_account(
+                &Feature {
+                    activated_at: Some(0),
+                },
+                lamports,
+            );
+            self.add_account(*feature_id, account);
+        }
+        self
+    }
+
+    /// Sets the default compute unit limit of transactions.
+    pub fn compute_unit_limit(&mut self, compute_unit_limit: u64) -> &mut Self {
+        self.genesis_validator
+            .compute_unit_limit(compute_unit_limit);
+        self
+    }
+
+    pub fn ticks_per_slot(&mut self, ticks_per_slot: u64) -> &mut Self {
+        self.genesis_validator.ticks_per_slot(ticks_per_slot);
+        self
+    }
+
+    /// Sets the length of epochs without the warmup period, at least 32 slots.
+    pub fn slots_per_epoch(&mut self, slots_per_epoch: u64) -> &mut Self {
+        self.genesis_validator.epoch_schedule(EpochSchedule::custom(
+            slots_per_epoch,
+            slots_per_epoch,
+            false,
+        ));
+        self
+    }
+
+    /// Sets the rent, call it before adding upgradeable programs so they are rent exempt.
+    pub fn rent(&mut self, rent: Rent) -> &mut Self {
+        self.genesis_validator.rent(rent);
+        self
+    }
+
+    pub fn fee_rate_governor(&mut self, fee_rate_governor: FeeRateGovernor) -> &mut Self {
+        self.genesis_validator.fee_rate_governor(fee_rate_governor);
+        self
+    }
+
     pub async fn start(&mut self) -> Client {
         let (rpc_addr, _) = request_local_address_rpc();
 
         self.start_faucet();
         self.start_admin_rcp(rpc_addr);
         self.genesis_validator.rpc_port(rpc_addr.port());
+        // The deactivated features are removed from the genesis after the activated ones are added
+        let deactivated_features = Vec::from_iter(self.deactivated_features.iter().copied());
+        self.genesis_validator
+            .deactivate_features(&deactivated_features);
 
         let (test_validator, payer) = self.genesis_validator.start_async().await;
         debug!("Starting test validator");
 
-        Client::new(payer, Arc::new(test_validator), self.ledger_path.clone())
+        let client = Client::new(payer, Arc::new(test_validator), self.ledger_path.clone());
+        if let Some(unix_timestamp) = self.unix_timestamp {
+            client.start_clock_at(unix_timestamp).await;
+        }
+        client
     }
 }
 
@@ -220,9 +449,14 @@ impl Default for Validator {
         genesis.max_genesis_archive_unpacked_size = Some(u64::MAX);
         genesis.max_ledger_shreds = Some(10_000);
 
-        Validator {
+        let mut validator = Validator {
             genesis_validator: genesis,
             ledger_path,
-        }
+            unix_timestamp: None,
+            deactivated_features: HashSet::new(),
+        };
+        validator.configure_runtime();
+        validator.add_fixtures();
+        validator
     }
 }
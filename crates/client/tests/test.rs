@@ -34,3 +34,159 @@ pub async fn generate_program_client() {
 
     assert_str_eq!(client_code, expected_client_code);
 }
+
+#[throws]
+#[tokio::test]
+pub async fn generate_fuzz_test() {
+    let expanded_anchor_program = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/test_data/expanded_anchor_program.rs"
+    ));
+
+    let expected_fuzz_test = include_str!(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/test_data/expected_fuzz_test.rs"
+    ));
+
+    let program_idl =
+        trdelnik_client::idl::parse_to_idl_program("turnstile".to_owned(), expanded_anchor_program)
+            .await?;
+    let idl = trdelnik_client::idl::Idl {
+        programs: vec![program_idl],
+    };
+
+    let fuzz_test = trdelnik_client::fuzz_test_generator::generate_source_code(idl);
+    let fuzz_test = trdelnik_client::Commander::format_program_code(&fuzz_test).await?;
+
+    assert_str_eq!(fuzz_test, expected_fuzz_test);
+}
+
+#[throws]
+#[tokio::test]
+pub async fn generate_fuzz_test_nested_accounts() {
+    use trdelnik_client::idl::*;
+
+    fn name(snake_case: &str, upper_camel_case: &str) -> IdlName {
+        IdlName {
+            snake_case: snake_case.to_owned(),
+            upper_camel_case: upper_camel_case.to_owned(),
+        }
+    }
+    fn account(name: &str, kind: IdlAccountKind) -> IdlAccountMeta {
+        IdlAccountMeta {
+            name: name.to_owned(),
+            checked: true,
+            kind,
+        }
+    }
+
+    let idl = Idl {
+        programs: vec![IdlProgram {
+            name: name("vault", "Vault"),
+            id: String::new(),
+            instruction_account_pairs: vec![(
+                IdlInstruction {
+                    name: name("deposit", "Deposit"),
+                    parameters: vec![
+                        ("amount".to_owned(), "u64".to_owned()),
+                        ("config".to_owned(), "Config".to_owned()),
+                    ],
+                },
+                IdlAccountGroup {
+                    name: name("deposit", "Deposit"),
+                    accounts: Vec::new(),
+                    account_metas: vec![
+                        account("authority", IdlAccountKind::Signer),
+                        account("inner.vault", IdlAccountKind::Pda),
+                        account("inner.user", IdlAccountKind::Created),
+                    ],
+                    nested_groups: vec![("inner".to_owned(), "Inner".to_owned())],
+                },
+            )],
+        }],
+    };
+
+    let fuzz_test = trdelnik_client::fuzz_test_generator::generate_source_code(idl);
+    let fuzz_test = trdelnik_client::Commander::format_program_code(&fuzz_test).await?;
+
+    assert!(!fuzz_test.contains("todo!"));
+    for expected in [
+        "amount: parameters.amount,",
+        "// TODO: set the `config` parameter\n            config: Default::default(),",
+        "authority: client.payer().pubkey(),",
+        "inner: vault::accounts::Inner {",
+        "// TODO: derive the `inner.vault` PDA\n                vault: Default::default(),",
+        "user: expected_state.inner_user.pubkey(),",
+        "[expected_state.inner_user.insecure_clone()],",
+        "inner_user: Arc::new(Keypair::new()),",
+    ] {
+        assert!(fuzz_test.contains(expected), "`{expected}` in:\n{fuzz_test}");
+    }
+}
+
+#[test]
+pub fn parse_anchor_error_logs() {
+    let logs = [
+        "Program Po1RaS8BEDbNcn5oXsFryAeQ6Wn8fvmE111DJaKCgPC invoke [1]",
+        "Program log: Instruction: Coin",
+        "Program log: AnchorError thrown in programs/turnstile/src/lib.rs:27. Error Code: Unauthorized. Error Number: 6000. Error Message: The signer is not allowed.",
+        "Program Po1RaS8BEDbNcn5oXsFryAeQ6Wn8fvmE111DJaKCgPC failed: custom program error: 0x1770",
+    ]
+    .map(str::to_owned)
+    .to_vec();
+    let error = trdelnik_client::AnchorErrorInfo::from_logs(Some(0), 6000, logs);
+    assert_eq!(error.name.as_deref(), Some("Unauthorized"));
+    assert_eq!(error.message.as_deref(), Some("The signer is not allowed"));
+    assert_eq!(
+        error.origin,
+        Some(trdelnik_client::AnchorErrorOrigin::Source {
+            file: "programs/turnstile/src/lib.rs".to_owned(),
+            line: 27,
+        })
+    );
+
+    let logs = vec![
+        "Program log: AnchorError caused by account: state. Error Code: ConstraintHasOne. Error Number: 2001. Error Message: A has one constraint was violated.".to_owned(),
+    ];
+    let error = trdelnik_client::AnchorErrorInfo::from_logs(Some(0), 2001, logs);
+    assert!(error.is(trdelnik_client::anchor_lang::error::ErrorCode::ConstraintHasOne));
+    assert_eq!(
+        error.origin,
+        Some(trdelnik_client::AnchorErrorOrigin::Account(
+            "state".to_owned()
+        ))
+    );
+
+    // A plain custom error without the Anchor log line
+    let error = trdelnik_client::AnchorErrorInfo::from_logs(Some(1), 1, vec![]);
+    assert_eq!(error.name, None);
+    assert_eq!(error.to_string(), "Custom(1)");
+}
+
+mod events {
+    use trdelnik_client::anchor_lang::{self, prelude::*};
+
+    #[event]
+    #[derive(Debug, PartialEq, Eq)]
+    pub struct CoinInserted {
+        pub amount: u64,
+    }
+}
+
+#[test]
+pub fn decode_anchor_events() {
+    use events::CoinInserted;
+    use trdelnik_client::anchor_lang::Event;
+
+    let event = CoinInserted { amount: 10 };
+    let logs = vec![
+        "Program log: Instruction: Coin".to_owned(),
+        format!("Program data: {}", base64::encode(event.data())),
+        // Data of another event
+        "Program data: AAAAAAAAAAAB".to_owned(),
+    ];
+    assert_eq!(
+        trdelnik_client::decode_events::<CoinInserted>(&logs),
+        vec![event]
+    );
+}
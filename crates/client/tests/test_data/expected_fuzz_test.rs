@@ -0,0 +1,98 @@
+use program_client::turnstile_instruction;
+use std::sync::Arc;
+use trdelnik_client::{
+    tokio, trdelnik_fuzz, Client, ClientError, FutureExt, Id, Keypair, Signer, System, Validator,
+};
+use trdelnik_fuzz::{Arbitrary, FuzzTestBuilder, Input, State};
+
+#[derive(Clone, Debug)]
+struct ExpectedState {
+    state: Arc<Keypair>,
+}
+
+#[derive(Debug, Clone, Arbitrary)]
+struct CoinParameters {
+    dummy_arg: String,
+}
+
+fn initialize_validator() -> Validator {
+    let mut validator = Validator::default();
+    validator.add_program("turnstile", turnstile_instruction::PROGRAM_ID);
+    validator
+}
+
+async fn init_handler(
+    _client: Client,
+    State(_expected_state): State<ExpectedState>,
+) -> Result<(), ClientError> {
+    // TODO: initialize the programs and the expected state
+    Ok(())
+}
+
+async fn flow_initialize(
+    client: Client,
+    State(expected_state): State<ExpectedState>,
+) -> Result<(), ClientError> {
+    turnstile_instruction::initialize(
+        &client,
+        turnstile::instruction::Initialize {},
+        turnstile::accounts::Initialize {
+            state: expected_state.state.pubkey(),
+            user: client.payer().pubkey(),
+            system_program: System::id(),
+        },
+        [expected_state.state.insecure_clone()],
+    )
+    .await?;
+    Ok(())
+}
+
+async fn flow_coin(
+    client: Client,
+    State(expected_state): State<ExpectedState>,
+    Input(parameters): Input<CoinParameters>,
+) -> Result<(), ClientError> {
+    turnstile_instruction::coin(
+        &client,
+        turnstile::instruction::Coin {
+            dummy_arg: parameters.dummy_arg,
+        },
+        turnstile::accounts::UpdateState {
+            state: expected_state.state.pubkey(),
+        },
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
+async fn flow_push(
+    client: Client,
+    State(expected_state): State<ExpectedState>,
+) -> Result<(), ClientError> {
+    turnstile_instruction::push(
+        &client,
+        turnstile::instruction::Push {},
+        turnstile::accounts::UpdateState {
+            state: expected_state.state.pubkey(),
+        },
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
+#[trdelnik_fuzz]
+async fn main() {
+    FuzzTestBuilder::new()
+        .initialize_validator(initialize_validator)
+        .add_init_handler(init_handler)
+        .add_flow(flow_initialize)
+        .add_flow(flow_coin)
+        .add_flow(flow_push)
+        .with_state(ExpectedState {
+            state: Arc::new(Keypair::new()),
+        })
+        .start(2, 200)
+        .await;
+}
@@ -0,0 +1,177 @@
+use rand::{
+    distributions::{Alphanumeric, DistString},
+    Rng,
+};
+use trdelnik_client::Pubkey;
+
+use crate::random::FuzzRng;
+
+/// Maximal length of the generated strings and vectors.
+const MAX_LENGTH: usize = 32;
+
+/// Values that can be generated by the fuzzer and received in flows through [Input](crate::Input).
+///
+/// It is implemented for primitive types, [Pubkey], [String], vectors, arrays, options and tuples,
+/// use `#[derive(Arbitrary)]` to implement it for your own structs and enums.
+pub trait Arbitrary: Sized {
+    fn arbitrary(rng: &mut FuzzRng) -> Self;
+}
+
+// Integers are biased towards the boundary values, which are the most likely to trigger overflows.
+macro_rules! impl_arbitrary_int {
+    ($($t:ty),*) => {
+        $(
+            impl Arbitrary for $t {
+                fn arbitrary(rng: &mut FuzzRng) -> Self {
+                    match rng.gen_range(0..16) {
+                        0 => <$t>::MIN,
+                        1 => <$t>::MAX,
+                        2 => 0,
+                        3 => 1,
+                        _ => rng.gen(),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_arbitrary_int!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+
+impl Arbitrary for bool {
+    fn arbitrary(rng: &mut FuzzRng) -> Self {
+        rng.gen()
+    }
+}
+
+// Floats are generated from random bits to cover the whole range including the subnormal
+// numbers, the special values are mixed in as they are unlikely to be hit by random bits.
+macro_rules! impl_arbitrary_float {
+    ($($t:ident),*) => {
+        $(
+            impl Arbitrary for $t {
+                fn arbitrary(rng: &mut FuzzRng) -> Self {
+                    match rng.gen_range(0..32) {
+                        0 => 0.,
+                        1 => -0.,
+                        2 => 1.,
+                        3 => $t::MIN,
+                        4 => $t::MAX,
+                        5 => $t::MIN_POSITIVE,
+                        6 => $t::EPSILON,
+                        7 => $t::INFINITY,
+                        8 => $t::NEG_INFINITY,
+                        9 => $t::NAN,
+                        _ => $t::from_bits(rng.gen()),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_arbitrary_float!(f32, f64);
+
+impl Arbitrary for Pubkey {
+    fn arbitrary(rng: &mut FuzzRng) -> Self {
+        Pubkey::new_from_array(rng.gen())
+    }
+}
+
+impl Arbitrary for String {
+    fn arbitrary(rng: &mut FuzzRng) -> Self {
+        let length = rng.gen_range(0..=MAX_LENGTH);
+        Alphanumeric.sample_string(rng, length)
+    }
+}
+
+impl<T: Arbitrary> Arbitrary for Vec<T> {
+    fn arbitrary(rng: &mut FuzzRng) -> Self {
+        let length = rng.gen_range(0..=MAX_LENGTH);
+        (0..length).map(|_| T::arbitrary(rng)).collect()
+    }
+}
+
+impl<T: Arbitrary> Arbitrary for Option<T> {
+    fn arbitrary(rng: &mut FuzzRng) -> Self {
+        rng.gen::<bool>().then(|| T::arbitrary(rng))
+    }
+}
+
+impl<T: Arbitrary, const N: usize> Arbitrary for [T; N] {
+    fn arbitrary(rng: &mut FuzzRng) -> Self {
+        std::array::from_fn(|_| T::arbitrary(rng))
+    }
+}
+
+macro_rules! impl_arbitrary_tuple {
+    ($($arg:ident)*) => {
+        impl<$($arg: Arbitrary),*> Arbitrary for ($($arg,)*) {
+            fn arbitrary(rng: &mut FuzzRng) -> Self {
+                ($($arg::arbitrary(rng),)*)
+            }
+        }
+    };
+}
+
+impl_arbitrary_tuple!(A);
+impl_arbitrary_tuple!(A B);
+impl_arbitrary_tuple!(A B C);
+impl_arbitrary_tuple!(A B C D);
+impl_arbitrary_tuple!(A B C D E);
+impl_arbitrary_tuple!(A B C D E F);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn seeded_rng() -> FuzzRng {
+        FuzzRng::Seeded(Box::new(StdRng::seed_from_u64(42)))
+    }
+
+    #[test]
+    fn test_int_boundaries() {
+        let mut rng = seeded_rng();
+        let values = (0..1000)
+            .map(|_| u64::arbitrary(&mut rng))
+            .collect::<Vec<_>>();
+        for boundary in [u64::MIN, u64::MAX, 1] {
+            assert!(values.contains(&boundary));
+        }
+    }
+
+    #[test]
+    fn test_float_range() {
+        let mut rng = seeded_rng();
+        let values = (0..1000)
+            .map(|_| f64::arbitrary(&mut rng))
+            .collect::<Vec<_>>();
+        assert!(values.iter().any(|value| value.is_nan()));
+        assert!(values.iter().any(|value| value.is_infinite()));
+        assert!(values.iter().any(|value| value.is_sign_negative()));
+        assert!(values.iter().any(|value| value.abs() > 1.));
+    }
+
+    #[test]
+    fn test_lengths() {
+        let mut rng = seeded_rng();
+        for _ in 0..100 {
+            assert!(String::arbitrary(&mut rng).len() <= MAX_LENGTH);
+            assert!(Vec::<u8>::arbitrary(&mut rng).len() <= MAX_LENGTH);
+            assert_eq!(<[Pubkey; 3]>::arbitrary(&mut rng).len(), 3);
+        }
+    }
+
+    #[test]
+    fn test_exhausted_input() {
+        let mut rng = FuzzRng::Input {
+            data: vec![],
+            position: 0,
+        };
+        assert_eq!(
+            <(i32, bool, Option<u8>, String)>::arbitrary(&mut rng),
+            (i32::MIN, false, None, String::new())
+        );
+    }
+}
@@ -0,0 +1,107 @@
+use std::{
+    sync::atomic::{AtomicBool, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Environment variable with the time budget of the run in the humantime format, e.g. `10m`,
+/// it is set by `trdelnik fuzz run --duration <DURATION>`.
+pub const DURATION_ENV_VAR: &str = "TRDELNIK_FUZZ_DURATION";
+
+/// Environment variable with the maximal number of executed flows of the run,
+/// it is set by `trdelnik fuzz run --iterations <ITERATIONS>`.
+pub const ITERATIONS_ENV_VAR: &str = "TRDELNIK_FUZZ_ITERATIONS";
+
+/// Environment variable with the number of sequences of the run, it overrides the `n_seq`
+/// argument of `FuzzTestBuilder::start` and is set by `trdelnik fuzz run --sequences <SEQUENCES>`.
+pub const SEQUENCES_ENV_VAR: &str = "TRDELNIK_FUZZ_SEQUENCES";
+
+/// Limits of the fuzz run shared by all the sequences.
+///
+/// When the time or the iteration budget is set, the run is continuous, i.e. finished
+/// sequences are restarted on fresh validators until the budget is exhausted.
+#[derive(Debug, Default)]
+pub(crate) struct Budget {
+    deadline: Option<Instant>,
+    max_iterations: Option<u64>,
+    iterations: AtomicU64,
+    stopped: AtomicBool,
+}
+
+impl Budget {
+    pub fn new(duration: Option<Duration>, max_iterations: Option<u64>) -> Self {
+        Self {
+            deadline: duration.map(|duration| Instant::now() + duration),
+            max_iterations,
+            ..Default::default()
+        }
+    }
+
+    pub fn is_continuous(&self) -> bool {
+        self.deadline.is_some() || self.max_iterations.is_some()
+    }
+
+    /// Stops all the sequences before their next flow, e.g. when one of them failed.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+            || self
+                .deadline
+                .map_or(false, |deadline| Instant::now() >= deadline)
+            || self.max_iterations.map_or(false, |max_iterations| {
+                self.iterations.load(Ordering::SeqCst) >= max_iterations
+            })
+    }
+
+    /// Takes one flow execution from the budget, returns `false` when the budget is exhausted.
+    pub fn take_iteration(&self) -> bool {
+        if self.stopped.load(Ordering::SeqCst)
+            || self
+                .deadline
+                .map_or(false, |deadline| Instant::now() >= deadline)
+        {
+            return false;
+        }
+        let iteration = self.iterations.fetch_add(1, Ordering::SeqCst);
+        self.max_iterations
+            .map_or(true, |max_iterations| iteration < max_iterations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iteration_budget() {
+        let budget = Budget::new(None, Some(3));
+        assert!(budget.is_continuous());
+        assert!((0..3).all(|_| budget.take_iteration()));
+        assert!(budget.is_exhausted());
+        assert!(!budget.take_iteration());
+    }
+
+    #[test]
+    fn test_time_budget() {
+        let budget = Budget::new(Some(Duration::from_millis(50)), None);
+        assert!(budget.is_continuous());
+        assert!(budget.take_iteration());
+        assert!(!budget.is_exhausted());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(budget.is_exhausted());
+        assert!(!budget.take_iteration());
+    }
+
+    #[test]
+    fn test_stop() {
+        let budget = Budget::new(None, None);
+        assert!(!budget.is_continuous());
+        assert!((0..100).all(|_| budget.take_iteration()));
+        assert!(!budget.is_exhausted());
+        budget.stop();
+        assert!(budget.is_exhausted());
+        assert!(!budget.take_iteration());
+    }
+}
@@ -1,11 +1,22 @@
 use anymap::{CloneAny, Map};
-use futures::FutureExt;
-use rand::seq::SliceRandom;
+use futures::{future, FutureExt};
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
 use std::fmt::Debug;
-use std::{future::Future, panic, pin::Pin, sync::Arc};
+use std::{
+    future::Future,
+    panic,
+    panic::AssertUnwindSafe,
+    path::PathBuf,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::task::JoinError;
 use tokio::{
-    runtime::Handle,
-    sync::{Mutex, OwnedMutexGuard, RwLock},
+    sync::{Mutex, OwnedMutexGuard, OwnedRwLockReadGuard, OwnedRwLockWriteGuard, RwLock},
     task,
 };
 use tracing::{debug, instrument};
@@ -15,28 +26,125 @@ use tracing_subscriber::{
     layer::{Layer, SubscriberExt},
     prelude::*,
 };
-use trdelnik_client::futures::future::select_all;
+use trdelnik_client::solana_sdk::signer::keypair::keypair_from_seed;
 use trdelnik_client::*;
 
-use crate::writer::MemoryWriter;
+use crate::arbitrary::Arbitrary;
+use crate::budget::{Budget, DURATION_ENV_VAR, ITERATIONS_ENV_VAR, SEQUENCES_ENV_VAR};
+use crate::corpus::{Corpus, CorpusCase, CorpusFlow, REPLAY_ENV_VAR};
+use crate::flow_result::{classify_error, FlowError, IntoFlowResult};
+use crate::model::{check_model, Model};
+use crate::random::{sequence_seed, with_rng, with_seeded_rng, SEED_ENV_VAR};
+use crate::shrink::minimize;
+use crate::stats::{FlowStats, RunStats, REPORT_ENV_VAR};
+use crate::writer::{MakeBySequenceWriter, SequenceLayer};
 
 type MyBoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
-type SimpleHandler = Box<dyn Fn(OwnedMutexGuard<PassableState>) -> MyBoxFuture<()> + Send + Sync>;
+type SimpleHandler =
+    Box<dyn Fn(OwnedMutexGuard<PassableState>) -> MyBoxFuture<anyhow::Result<()>> + Send + Sync>;
+type SimpleGuard = Box<dyn Fn(OwnedMutexGuard<PassableState>) -> MyBoxFuture<bool> + Send + Sync>;
 
 type CreateValidatorHandler = fn() -> Validator;
 
+type SequenceHistory = Arc<std::sync::Mutex<Vec<ExecutedFlow>>>;
+
+/// The handlers and statistics shared by all the sequences of the run.
+#[derive(Clone)]
+struct SequenceContext {
+    flows: Arc<RwLock<Vec<Flow>>>,
+    invariants: Arc<RwLock<Vec<SimpleHandler>>>,
+    init_handlers: Arc<RwLock<Vec<SimpleHandler>>>,
+    stats: Arc<std::sync::Mutex<RunStats>>,
+}
+
+struct SequenceFailure {
+    curr_seq_n: usize,
+    seed: u64,
+    /// Seed the init handlers were executed with, it differs from the `seed`
+    /// when the sequence was started from a validator snapshot.
+    init_seed: u64,
+    history: SequenceHistory,
+    error: JoinError,
+}
+
+/// How the sequence gets to the state after the init handlers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SequenceInit {
+    Run,
+    /// Runs the init handlers and captures the state for the following sequences.
+    RunAndCapture,
+    /// The state after the init handlers was restored from a snapshot.
+    Restored,
+}
+
+/// The state of a sequence right after its init handlers, it is restored for the following
+/// sequences when the validator snapshots are enabled.
+#[derive(Clone)]
+struct InitializedState {
+    passable_state: PassableState,
+    snapshot: ValidatorSnapshot,
+    seed: u64,
+}
+
+struct Flow {
+    name: &'static str,
+    handler: SimpleHandler,
+    weight: u32,
+    guard: Option<SimpleGuard>,
+    /// Error codes declared by the `expect_flow_errors` method, any code is expected if `None`.
+    expected_errors: Option<Vec<u32>>,
+}
+
+impl Flow {
+    async fn is_enabled(&self, passable_state: Arc<Mutex<PassableState>>) -> bool {
+        match &self.guard {
+            Some(guard) => guard(passable_state.lock_owned().await).await,
+            None => true,
+        }
+    }
+}
+
+/// A flow executed in a sequence, all the random inputs of the flow are generated from its `seed`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutedFlow {
+    /// Index of the flow in the order of registration using the `add_flow` method.
+    pub flow: usize,
+    pub seed: u64,
+    /// Debug representations of the [Input]s generated for the flow.
+    pub inputs: Vec<String>,
+}
+
 pub struct FuzzTestBuilder {
-    flows: Arc<RwLock<Vec<SimpleHandler>>>,
+    flows: Arc<RwLock<Vec<Flow>>>,
     invariants: Arc<RwLock<Vec<SimpleHandler>>>,
     init_handlers: Arc<RwLock<Vec<SimpleHandler>>>,
     started: bool,
     validator_create_handler: Option<CreateValidatorHandler>,
     passable_state: PassableState,
+    seed: Option<u64>,
+    shrink: bool,
+    stats: Arc<std::sync::Mutex<RunStats>>,
+    log_dir: Option<PathBuf>,
+    log_targets: Vec<String>,
+    duration: Option<Duration>,
+    max_iterations: Option<u64>,
+    report_path: Option<PathBuf>,
+    snapshots: bool,
+}
+
+/// Funded keypairs the flows are performed by, see the [Actor] extractor.
+#[derive(Default)]
+struct ActorPool {
+    keypairs: Vec<Keypair>,
+    lamports: u64,
 }
 
 pub struct PassableState {
     state: Map<dyn CloneAny + Send + Sync>,
     client: Option<Client>,
+    current_flow: Option<&'static str>,
+    history: Option<SequenceHistory>,
+    actors: Arc<ActorPool>,
 }
 
 impl Clone for PassableState {
@@ -44,6 +152,9 @@ impl Clone for PassableState {
         PassableState {
             state: self.state.clone(),
             client: self.client.clone(),
+            current_flow: self.current_flow,
+            history: self.history.clone(),
+            actors: self.actors.clone(),
         }
     }
 }
@@ -55,29 +166,46 @@ impl PassableState {
             .expect("You probably forgot to call the `start` method before accessing the client.")
             .clone()
     }
+
+    /// Logs the value generated for the current flow and stores it in the history of the sequence.
+    fn record_input(&self, value_repr: String) {
+        if let Some(flow_name) = self.current_flow {
+            debug!("Generated input for flow {}: {}", flow_name, value_repr);
+            if let Some(history) = &self.history {
+                if let Some(executed_flow) = history.lock().unwrap().last_mut() {
+                    executed_flow.inputs.push(value_repr);
+                }
+            }
+        } else {
+            debug!("Generated input: {}", value_repr);
+        }
+    }
 }
 
-struct CustomArcMutex<T: Clone>(Arc<Mutex<T>>);
+/// The state registered using the `with_state` method, locked by the [State] and [StateRef] extractors.
+///
+/// Cloning it clones the value, so every sequence works with its own copy of the state.
+struct SharedState<T>(Arc<RwLock<T>>);
 
-impl<T: Clone> CustomArcMutex<T> {
-    fn clone_arc(&self) -> Arc<Mutex<T>> {
-        self.0.clone()
+impl<T> SharedState<T> {
+    fn new(t: T) -> Self {
+        Self(Arc::new(RwLock::new(t)))
     }
 
-    fn new(t: T) -> Self {
-        Self(Arc::new(Mutex::new(t)))
+    fn clone_arc(&self) -> Arc<RwLock<T>> {
+        self.0.clone()
     }
 }
 
-impl<T: Clone> Clone for CustomArcMutex<T> {
+impl<T: Clone> Clone for SharedState<T> {
     fn clone(&self) -> Self {
-        let test = &self.0;
-        // Unfortunately this version of tokio does not support blocking locks in async runtime.
-        let lock = task::block_in_place(move || {
-            Handle::current().block_on(async move { test.clone().lock_owned().await })
-        });
-
-        CustomArcMutex(Arc::new(Mutex::new(lock.clone())))
+        // The state is cloned only between the sequences, when no handler holds its lock
+        let value = self
+            .0
+            .try_read()
+            .expect("The state cannot be cloned while it is locked by a handler")
+            .clone();
+        SharedState::new(value)
     }
 }
 
@@ -95,13 +223,43 @@ impl FuzzTestBuilder {
             invariants: Arc::new(RwLock::new(vec![])),
             init_handlers: Arc::new(RwLock::new(vec![])),
             validator_create_handler: None,
+            seed: None,
+            shrink: true,
+            stats: Default::default(),
+            log_dir: None,
+            log_targets: default_log_targets(),
+            duration: None,
+            max_iterations: None,
+            report_path: None,
+            snapshots: false,
             passable_state: PassableState {
                 state: Map::<dyn CloneAny + Send + Sync>::new(),
                 client: None,
+                current_flow: None,
+                history: None,
+                actors: Default::default(),
             },
         }
     }
 
+    fn box_handler<F, Args>(handler: F) -> SimpleHandler
+    where
+        F: Handler<Args> + 'static + Sync + Send,
+    {
+        Box::new(move |passable_state: OwnedMutexGuard<PassableState>| {
+            let f = handler.clone();
+            Box::pin(async move { f.call(passable_state).await })
+        })
+    }
+
+    fn push_handler<T: Send + Sync + 'static>(array: Arc<RwLock<Vec<T>>>, handler: T) {
+        // The handlers are only read by the sequences, which do not run before the `start` method
+        array
+            .try_write()
+            .expect("Handlers cannot be added while the sequences are running")
+            .push(handler);
+    }
+
     fn add_handler<F, Args>(
         &mut self,
         array: Arc<RwLock<Vec<SimpleHandler>>>,
@@ -110,21 +268,7 @@ impl FuzzTestBuilder {
     where
         F: Handler<Args> + 'static + Sync + Send,
     {
-        let boxed_invariant: SimpleHandler =
-            Box::new(move |passable_state: OwnedMutexGuard<PassableState>| {
-                let f = handler.clone();
-                Box::pin(async move {
-                    f.call(passable_state).await;
-                })
-            });
-        {
-            task::block_in_place(move || {
-                Handle::current().block_on(async move {
-                    let mut locked_invariants = array.write().await;
-                    locked_invariants.push(boxed_invariant);
-                })
-            });
-        }
+        Self::push_handler(array, Self::box_handler(handler));
         self
     }
 
@@ -139,14 +283,116 @@ impl FuzzTestBuilder {
         self
     }
 
-    pub fn add_flow<F, Args>(&mut self, flow: F) -> &mut Self
+    fn push_flow<F, Args>(&mut self, flow: F, weight: u32, guard: Option<SimpleGuard>)
     where
         F: Handler<Args> + 'static + Sync + Send,
     {
         if self.started {
             panic!("You cannot add flows after the `start` method was called.");
         }
-        self.add_handler(self.flows.clone(), flow);
+        let name = std::any::type_name::<F>();
+        self.stats.lock().unwrap().flows.push(FlowStats::new(name));
+        let flow = Flow {
+            name,
+            handler: Self::box_handler(flow),
+            weight,
+            guard,
+            expected_errors: None,
+        };
+        Self::push_handler(self.flows.clone(), flow);
+    }
+
+    pub fn add_flow<F, Args>(&mut self, flow: F) -> &mut Self
+    where
+        F: Handler<Args> + 'static + Sync + Send,
+    {
+        self.push_flow(flow, 1, None);
+        self
+    }
+
+    /// Adds a flow which is selected with the probability proportional to its `weight`,
+    /// flows added using the `add_flow` method have the weight 1.
+    pub fn add_flow_weighted<F, Args>(&mut self, flow: F, weight: u32) -> &mut Self
+    where
+        F: Handler<Args> + 'static + Sync + Send,
+    {
+        self.push_flow(flow, weight, None);
+        self
+    }
+
+    /// Adds a weighted flow which can be selected only when the `guard` returns `true`.
+    ///
+    /// The guard receives the same arguments as flows, e.g. `Client` and `State<T>`.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// async fn is_locked(State(state): State<TurnstileExpectedState>) -> bool {
+    ///     state.locked
+    /// }
+    ///
+    /// FuzzTestBuilder::new()
+    ///     .add_guarded_flow(flow_coin, 3, is_locked)
+    /// ```
+    pub fn add_guarded_flow<F, Args, G, GArgs>(
+        &mut self,
+        flow: F,
+        weight: u32,
+        guard: G,
+    ) -> &mut Self
+    where
+        F: Handler<Args> + 'static + Sync + Send,
+        G: Guard<GArgs> + 'static + Sync + Send,
+    {
+        let boxed_guard: SimpleGuard =
+            Box::new(move |passable_state: OwnedMutexGuard<PassableState>| {
+                let g = guard.clone();
+                Box::pin(async move { g.call(passable_state).await })
+            });
+        self.push_flow(flow, weight, Some(boxed_guard));
+        self
+    }
+
+    /// Declares the program errors the flow is expected to fail with, e.g. `TurnstileError::Unauthorized`.
+    ///
+    /// By default, every program error returned by a flow is an expected rejection. Once the errors
+    /// of the flow are declared, a program error with any other code fails the sequence.
+    ///
+    /// # Example
+    ///
+    /// ```rust,ignore
+    /// FuzzTestBuilder::new()
+    ///     .add_flow(flow_withdraw)
+    ///     .expect_flow_errors(flow_withdraw, [TurnstileError::Unauthorized])
+    /// ```
+    pub fn expect_flow_errors<F, Args, E>(
+        &mut self,
+        _flow: F,
+        errors: impl IntoIterator<Item = E>,
+    ) -> &mut Self
+    where
+        F: Handler<Args> + 'static + Sync + Send,
+        E: Into<u32>,
+    {
+        if self.started {
+            panic!("You cannot declare the errors of flows after the `start` method was called.");
+        }
+        let name = std::any::type_name::<F>();
+        {
+            let mut flows = self
+                .flows
+                .try_write()
+                .expect("Handlers cannot be changed while the sequences are running");
+            let flow = flows
+                .iter_mut()
+                .find(|flow| flow.name == name)
+                .unwrap_or_else(|| {
+                    panic!("Flow {name} is not registered, add it before declaring its errors")
+                });
+            flow.expected_errors
+                .get_or_insert_with(Vec::new)
+                .extend(errors.into_iter().map(Into::into));
+        }
         self
     }
 
@@ -161,72 +407,485 @@ impl FuzzTestBuilder {
         self
     }
 
+    /// Creates a pool of `n_actors` keypairs, each of them is funded with `lamports`
+    /// on every validator before the init handlers run.
+    ///
+    /// Flows receive a random actor through the [Actor] extractor. The keypairs are derived
+    /// from the index of the actor, so they are the same in every run.
+    pub fn with_actors(&mut self, n_actors: usize, lamports: u64) -> &mut Self {
+        if self.started {
+            panic!("You cannot add actors after the `start` method was called.");
+        }
+        let keypairs = (0..n_actors)
+            .map(|index| {
+                let mut seed = [0u8; 32];
+                seed[..8].copy_from_slice(&(index as u64 + 1).to_le_bytes());
+                keypair_from_seed(&seed).expect("Keypair generation from seed failed")
+            })
+            .collect();
+        self.passable_state.actors = Arc::new(ActorPool { keypairs, lamports });
+        self
+    }
+
+    /// Registers the model of on-chain accounts, see [Model].
+    ///
+    /// The model is available in flows as `State<M>`. After every flow, the accounts watched
+    /// by the model are fetched and compared with it field by field, any difference fails the sequence.
+    pub fn with_model<M: Model>(&mut self, model: M) -> &mut Self {
+        self.with_state(model);
+        self.add_invariant(check_model::<M>)
+    }
+
     pub fn initialize_validator(&mut self, create_handler: CreateValidatorHandler) -> &mut Self {
         self.validator_create_handler = Some(create_handler);
         self
     }
 
+    /// Sets the master seed of the run, each sequence derives its own seed from it.
+    ///
+    /// The seed passed through `trdelnik fuzz run --seed <SEED>` takes precedence.
+    /// When no seed is set, a random one is generated and printed at the start.
+    pub fn with_seed(&mut self, seed: u64) -> &mut Self {
+        if self.started {
+            panic!("You cannot set the seed after the `start` method was called.");
+        }
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Enables or disables the minimization of failing sequences, it is enabled by default.
+    ///
+    /// When a sequence fails, it is re-executed on fresh validators with subsets of its flows
+    /// to find the shortest sequence of flows still violating the invariants.
+    pub fn with_shrinking(&mut self, shrink: bool) -> &mut Self {
+        self.shrink = shrink;
+        self
+    }
+
+    /// Writes the log of every sequence to `<log_dir>/sequence-<index>.log` at the end of the run.
+    ///
+    /// Only the log of the failing sequence is printed to stdout.
+    pub fn with_log_dir(&mut self, log_dir: impl Into<PathBuf>) -> &mut Self {
+        self.log_dir = Some(log_dir.into());
+        self
+    }
+
+    /// Includes the events of the `target` module and its submodules in the logs of the sequences.
+    ///
+    /// The events of `trdelnik_fuzz` and of the fuzz test crate are logged by default,
+    /// add e.g. the crate with the helpers shared by the fuzz tests.
+    pub fn with_log_target(&mut self, target: impl Into<String>) -> &mut Self {
+        self.log_targets.push(target.into());
+        self
+    }
+
+    /// Runs the sequences continuously until the time budget is exhausted.
+    ///
+    /// `start(n_seq, n_flows)` then keeps `n_seq` sequences running in parallel, every finished
+    /// sequence is restarted on a fresh validator. The duration passed through
+    /// `trdelnik fuzz run --duration <DURATION>` takes precedence.
+    pub fn with_duration(&mut self, duration: Duration) -> &mut Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Runs the sequences continuously until `max_iterations` flows are executed in total.
+    ///
+    /// The limit passed through `trdelnik fuzz run --iterations <ITERATIONS>` takes precedence.
+    pub fn with_max_iterations(&mut self, max_iterations: u64) -> &mut Self {
+        self.max_iterations = Some(max_iterations);
+        self
+    }
+
+    /// Writes the end-of-run report as JSON to the file, the report is always printed to stdout.
+    ///
+    /// The path passed through `trdelnik fuzz run --report <PATH>` takes precedence.
+    pub fn with_report(&mut self, report_path: impl Into<PathBuf>) -> &mut Self {
+        self.report_path = Some(report_path.into());
+        self
+    }
+
+    /// Reuses the validator for the following sequences instead of starting a new one.
+    ///
+    /// The accounts of the validator and the state are captured after the init handlers
+    /// of the first sequence and restored before every following sequence, the init handlers
+    /// are not executed again. The timestamp of the clock is restored, the slot keeps increasing.
+    pub fn with_validator_snapshots(&mut self, snapshots: bool) -> &mut Self {
+        self.snapshots = snapshots;
+        self
+    }
+
+    fn budget(&self) -> Budget {
+        let duration = match std::env::var(DURATION_ENV_VAR) {
+            Ok(duration) => Some(humantime::parse_duration(&duration).unwrap_or_else(|e| {
+                panic!("Invalid duration in the {DURATION_ENV_VAR} variable: {e}")
+            })),
+            Err(_) => self.duration,
+        };
+        let max_iterations = match std::env::var(ITERATIONS_ENV_VAR) {
+            Ok(max_iterations) => Some(max_iterations.parse().unwrap_or_else(|_| {
+                panic!("Invalid number in the {ITERATIONS_ENV_VAR} variable: {max_iterations}")
+            })),
+            Err(_) => self.max_iterations,
+        };
+        Budget::new(duration, max_iterations)
+    }
+
+    /// The number of sequences passed through `trdelnik fuzz run --sequences <SEQUENCES>` or `n_seq`.
+    fn n_sequences(n_seq: usize) -> usize {
+        match std::env::var(SEQUENCES_ENV_VAR) {
+            Ok(n_sequences) => n_sequences.parse().unwrap_or_else(|_| {
+                panic!("Invalid number in the {SEQUENCES_ENV_VAR} variable: {n_sequences}")
+            }),
+            Err(_) => n_seq,
+        }
+    }
+
+    fn report_path(&self) -> Option<PathBuf> {
+        std::env::var_os(REPORT_ENV_VAR)
+            .map(PathBuf::from)
+            .or_else(|| self.report_path.clone())
+    }
+
+    fn context(&self) -> SequenceContext {
+        SequenceContext {
+            flows: self.flows.clone(),
+            invariants: self.invariants.clone(),
+            init_handlers: self.init_handlers.clone(),
+            stats: self.stats.clone(),
+        }
+    }
+
+    fn master_seed(&self) -> u64 {
+        match std::env::var(SEED_ENV_VAR) {
+            Ok(seed) => seed
+                .parse()
+                .unwrap_or_else(|_| panic!("Invalid seed in the {SEED_ENV_VAR} variable: {seed}")),
+            Err(_) => self.seed.unwrap_or_else(|| rand::thread_rng().gen()),
+        }
+    }
+
     pub fn with_state<S: Send + Sync + Clone + 'static>(&mut self, state: S) -> &mut Self {
         if self.started {
             panic!("You cannot add state after the `start` method was called.");
         }
 
-        self.passable_state.state.insert(CustomArcMutex::new(state));
+        self.passable_state.state.insert(SharedState::new(state));
         self
     }
 
     async fn run_rand_flow(
         passable_state: Arc<Mutex<PassableState>>,
-        flows: Arc<RwLock<Vec<SimpleHandler>>>,
-        invariants: Arc<RwLock<Vec<SimpleHandler>>>,
-    ) {
+        context: &SequenceContext,
+        history: SequenceHistory,
+    ) -> bool {
+        let mut enabled_flows = vec![];
         {
-            let owned_mg_passable_state = passable_state.clone().lock_owned().await;
-            let read_flows = flows.read().await;
-            let flow = read_flows
-                .choose(&mut rand::thread_rng())
-                .expect("There are no flows to run, add them using the `add_flow` method.");
-            debug!("Started flow");
-            flow(owned_mg_passable_state).await;
-            debug!("Stopped flow");
+            let read_flows = context.flows.read().await;
+            if read_flows.is_empty() {
+                panic!("There are no flows to run, add them using the `add_flow` method.");
+            }
+            for (i, flow) in read_flows.iter().enumerate() {
+                if flow.weight > 0 && flow.is_enabled(passable_state.clone()).await {
+                    enabled_flows.push((i, flow.weight));
+                } else {
+                    context.stats.lock().unwrap().flows[i].disabled += 1;
+                }
+            }
+        }
+        if enabled_flows.is_empty() {
+            debug!("There are no enabled flows, stopping the sequence");
+            return false;
         }
 
-        debug!("Checking invariants...");
-        let invariants = invariants.read().await;
-        for invariant in invariants.iter() {
-            let owned_mg_passable_state = passable_state.clone().lock_owned().await;
-            invariant(owned_mg_passable_state).await;
+        let weights = WeightedIndex::new(enabled_flows.iter().map(|(_, weight)| weight))
+            .expect("Invalid flow weights");
+        let (flow, seed) = with_rng(|rng| (enabled_flows[weights.sample(rng)].0, rng.gen()));
+        history.lock().unwrap().push(ExecutedFlow {
+            flow,
+            seed,
+            inputs: vec![],
+        });
+        context.stats.lock().unwrap().flows[flow].executions += 1;
+
+        Self::run_flow(passable_state, context, flow, seed).await;
+        true
+    }
+
+    async fn run_flow(
+        passable_state: Arc<Mutex<PassableState>>,
+        context: &SequenceContext,
+        flow: usize,
+        seed: u64,
+    ) {
+        with_seeded_rng(seed, async move {
+            {
+                let mut owned_mg_passable_state = passable_state.clone().lock_owned().await;
+                let read_flows = context.flows.read().await;
+                let flow_name = read_flows[flow].name;
+                debug!("Started flow {}", flow_name);
+                owned_mg_passable_state.current_flow = Some(flow_name);
+                if let Err(e) = (read_flows[flow].handler)(owned_mg_passable_state).await {
+                    match classify_error(&e, read_flows[flow].expected_errors.as_deref()) {
+                        FlowError::Rejected(code) => {
+                            debug!("Flow {} rejected with {}: {:?}", flow_name, code, e);
+                            context.stats.lock().unwrap().flows[flow].record_failure(code);
+                        }
+                        FlowError::Unexpected => {
+                            panic!("Flow {flow_name} failed with an unexpected error: {e:?}")
+                        }
+                    }
+                }
+                debug!("Stopped flow");
+            }
+
+            debug!("Checking invariants...");
+            let invariants = context.invariants.read().await;
+            for invariant in invariants.iter() {
+                let mut owned_mg_passable_state = passable_state.clone().lock_owned().await;
+                owned_mg_passable_state.current_flow = None;
+                if let Err(e) = invariant(owned_mg_passable_state).await {
+                    panic!("Invariant failed: {e:?}");
+                }
+                context.stats.lock().unwrap().invariant_checks += 1;
+            }
+            debug!("Invariants passed");
+        })
+        .await
+    }
+
+    async fn run_init_handlers(
+        passable_state: Arc<Mutex<PassableState>>,
+        context: &SequenceContext,
+    ) {
+        let (client, actors) = {
+            let passable_state = passable_state.lock().await;
+            (passable_state.client(), passable_state.actors.clone())
+        };
+        for actor in actors.keypairs.iter() {
+            client
+                .airdrop(actor.pubkey(), actors.lamports)
+                .await
+                .expect("Unable to fund the actor");
+        }
+
+        for handler in context.init_handlers.read().await.iter() {
+            let passable_state_new = passable_state.clone().lock_owned().await;
+            if let Err(e) = handler(passable_state_new).await {
+                panic!("Init handler failed: {e:?}");
+            }
         }
-        debug!("Invariants passed");
     }
 
     #[instrument(
         name = "Sequence::started",
-        skip(thread_safe_passed_state, flows, invariants, n_flows, _curr_seq_n, init_handlers)
-        fields(curr_sequence_number = %_curr_seq_n)
+        skip(thread_safe_passed_state, context, budget, n_flows, curr_seq_n, seed, history, init)
+        fields(curr_sequence_number = %curr_seq_n, seed = %seed)
     )]
+    #[allow(clippy::too_many_arguments)]
     async fn run_sequence(
-        _curr_seq_n: usize,
+        curr_seq_n: usize,
+        seed: u64,
         n_flows: usize,
         thread_safe_passed_state: Arc<Mutex<PassableState>>,
-        flows: Arc<RwLock<Vec<SimpleHandler>>>,
-        invariants: Arc<RwLock<Vec<SimpleHandler>>>,
-        init_handlers: Arc<RwLock<Vec<SimpleHandler>>>,
-    ) {
-        // Initialize the handlers
-        for handler in init_handlers.read().await.iter() {
-            let passable_state_new = thread_safe_passed_state.clone().lock_owned().await;
-            handler(passable_state_new).await;
+        context: SequenceContext,
+        budget: Arc<Budget>,
+        history: SequenceHistory,
+        init: SequenceInit,
+    ) -> Option<InitializedState> {
+        debug!("Running sequence {} with seed {}", curr_seq_n + 1, seed);
+        context.stats.lock().unwrap().sequences += 1;
+        let mut initialized = None;
+        if init != SequenceInit::Restored {
+            Self::run_init_handlers(thread_safe_passed_state.clone(), &context).await;
+        }
+        if init == SequenceInit::RunAndCapture {
+            let passable_state = thread_safe_passed_state.lock().await.clone();
+            debug!("Capturing the validator snapshot");
+            let snapshot = passable_state.client().snapshot().await;
+            initialized = Some(InitializedState {
+                passable_state,
+                snapshot,
+                seed,
+            });
         }
 
         for i in 0..n_flows {
+            if !budget.take_iteration() {
+                debug!("The budget of the run is exhausted, stopping the sequence");
+                break;
+            }
             debug!("Running flow {}/{}", i + 1, n_flows);
-            Self::run_rand_flow(
-                thread_safe_passed_state.clone(),
-                flows.clone(),
-                invariants.clone(),
+            let flow_executed =
+                Self::run_rand_flow(thread_safe_passed_state.clone(), &context, history.clone())
+                    .await;
+            if !flow_executed {
+                break;
+            }
+        }
+        initialized
+    }
+
+    /// Executes the init handlers and the given flows in order on a fresh validator.
+    ///
+    /// Returns `true` when a flow or an invariant panicked. Sequences containing a flow
+    /// which is not enabled by its guard at the time of execution are considered passing.
+    #[instrument(name = "Sequence::replay", skip(self, sequence), fields(seed = %seed))]
+    async fn replay_fails(&self, seed: u64, sequence: Vec<ExecutedFlow>) -> bool {
+        let create_handler = self
+            .validator_create_handler
+            .expect("You need to specify the creator of the validator using the `initialize_validator` method.");
+        let mut validator = create_handler();
+        let mut passable_state = self.passable_state.clone();
+        passable_state.client = Some(validator.start().await);
+        let passable_state = Arc::new(Mutex::new(passable_state));
+
+        let context = self.context();
+        let replay = async move {
+            with_seeded_rng(
+                seed,
+                Self::run_init_handlers(passable_state.clone(), &context),
             )
             .await;
+            for executed_flow in sequence {
+                let enabled = context.flows.read().await[executed_flow.flow]
+                    .is_enabled(passable_state.clone())
+                    .await;
+                if !enabled {
+                    debug!(
+                        "Flow {} is not enabled, stopping the replay",
+                        executed_flow.flow
+                    );
+                    return;
+                }
+                Self::run_flow(
+                    passable_state.clone(),
+                    &context,
+                    executed_flow.flow,
+                    executed_flow.seed,
+                )
+                .await;
+            }
+        };
+        AssertUnwindSafe(replay).catch_unwind().await.is_err()
+    }
+
+    /// Finds the shortest subsequence of the failing sequence which still fails.
+    ///
+    /// Returns the original sequence when the failure is not reproducible.
+    async fn shrink_sequence(&self, seed: u64, sequence: Vec<ExecutedFlow>) -> Vec<ExecutedFlow> {
+        println!(
+            "Minimizing the failing sequence of {} flows...",
+            sequence.len()
+        );
+        // The panics of the replayed sequences are expected, do not print them
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(|_| {}));
+
+        let minimized = if self.replay_fails(seed, sequence.clone()).await {
+            Some(
+                minimize(sequence.clone(), |candidate| {
+                    self.replay_fails(seed, candidate)
+                })
+                .await,
+            )
+        } else {
+            None
+        };
+
+        panic::set_hook(default_hook);
+
+        match minimized {
+            Some(minimized) => {
+                let flows = self.flows.read().await;
+                println!("Shortest failing sequence ({} flows):", minimized.len());
+                for (i, executed_flow) in minimized.iter().enumerate() {
+                    println!(
+                        "  {}. {} (seed {}) {}",
+                        i + 1,
+                        flows[executed_flow.flow].name,
+                        executed_flow.seed,
+                        executed_flow.inputs.join(", ")
+                    );
+                }
+                minimized
+            }
+            None => {
+                println!("The failure is not reproducible, skipping the minimization");
+                sequence
+            }
+        }
+    }
+
+    /// Stores the failing sequence in the corpus of the fuzz test, so it can be replayed
+    /// using `trdelnik fuzz replay <TEST_NAME>`.
+    ///
+    /// The init handlers are replayed with the `init_seed`, the case is named by the `seed`
+    /// of the failing sequence and the hash of its flows.
+    async fn save_to_corpus(&self, seed: u64, init_seed: u64, sequence: &[ExecutedFlow]) {
+        let flows = self.flows.read().await;
+        let case = CorpusCase {
+            seed: init_seed,
+            flows: sequence
+                .iter()
+                .map(|executed_flow| CorpusFlow {
+                    name: flows[executed_flow.flow].name.to_owned(),
+                    seed: executed_flow.seed,
+                    inputs: executed_flow.inputs.clone(),
+                })
+                .collect(),
+        };
+        match Corpus::from_env().save(&case.name(seed), &case).await {
+            Ok(path) => println!("Failing sequence saved to {}", path.display()),
+            Err(e) => println!("Unable to save the failing sequence to the corpus: {e}"),
+        }
+    }
+
+    /// Re-executes the cases stored in the corpus of the fuzz test against fresh validators.
+    ///
+    /// Replays all the stored cases when `case_name` is `None`.
+    /// Panics when any of the cases fails, so the stored crashes work as regression tests.
+    pub async fn replay(&mut self, case_name: Option<&str>) {
+        self.started = true;
+        let corpus = Corpus::from_env();
+        let cases = corpus.load(case_name).await.unwrap_or_else(|e| {
+            panic!(
+                "Unable to load the corpus from {}: {e}",
+                corpus.dir().display()
+            )
+        });
+
+        let mut n_failed = 0;
+        for (name, case) in cases.iter() {
+            let sequence = {
+                let flows = self.flows.read().await;
+                case.flows
+                    .iter()
+                    .map(|corpus_flow| ExecutedFlow {
+                        flow: flows
+                            .iter()
+                            .position(|flow| flow.name == corpus_flow.name)
+                            .unwrap_or_else(|| {
+                                panic!(
+                                    "Flow {} from the case {name} is not registered",
+                                    corpus_flow.name
+                                )
+                            }),
+                        seed: corpus_flow.seed,
+                        inputs: corpus_flow.inputs.clone(),
+                    })
+                    .collect::<Vec<_>>()
+            };
+            if self.replay_fails(case.seed, sequence).await {
+                n_failed += 1;
+                println!("Case {name} failed");
+            } else {
+                println!("Case {name} passed");
+            }
+        }
+
+        if n_failed > 0 {
+            panic!("{n_failed} of {} replayed cases failed", cases.len());
         }
     }
 
@@ -236,120 +895,307 @@ impl FuzzTestBuilder {
             panic!("You need to specify the creator of the validator using the `initialize_validator` method.");
         }
 
-        let writer = MemoryWriter::new();
+        if let Ok(case_name) = std::env::var(REPLAY_ENV_VAR) {
+            let case_name = (!case_name.is_empty()).then_some(case_name);
+            return self.replay(case_name.as_deref()).await;
+        }
 
-        let mut futures = vec![];
+        let master_seed = self.master_seed();
+        let n_seq = Self::n_sequences(n_seq);
+        println!("Fuzzing with seed {master_seed}");
+        let budget = Arc::new(self.budget());
+
+        let writer = MakeBySequenceWriter::new();
 
         let format = format()
             .pretty()
             .with_thread_ids(true)
             .with_thread_names(true);
+        let log_targets = self.log_targets.clone();
         let layer = tracing_subscriber::fmt::layer()
             .event_format(format)
             .with_writer(writer.clone())
-            .with_filter(filter_fn(|metadata| {
-                metadata.target() == "trdelnik_fuzz::builder"
+            .with_filter(filter_fn(move |metadata| {
+                is_log_target(&log_targets, metadata.target())
             }));
 
-        tracing_subscriber::registry().with(layer).init();
+        tracing_subscriber::registry()
+            .with(SequenceLayer)
+            .with(layer)
+            .init();
 
-        let local = task::LocalSet::new();
+        let started_at = Instant::now();
+        let next_seq_n = Arc::new(AtomicUsize::new(0));
+        let failure = Arc::new(std::sync::Mutex::new(None));
 
-        let clients = Arc::new(Mutex::new(vec![]));
+        // Every parallel sequence is driven by a local task starting its validator,
+        // in the continuous mode the sequence is restarted on a fresh validator.
+        let local = task::LocalSet::new();
         for _ in 0..n_seq {
             let create_handler = self.validator_create_handler.unwrap();
-            let clients = clients.clone();
+            let snapshots = self.snapshots;
+            let passable_state = self.passable_state.clone();
+            let context = self.context();
+            let budget = budget.clone();
+            let next_seq_n = next_seq_n.clone();
+            let failure = failure.clone();
 
             local.spawn_local(async move {
-                let mut validator = create_handler();
-                let client = validator.start().await;
-                clients.lock().await.push(client);
+                let mut initialized: Option<InitializedState> = None;
+                loop {
+                    let curr_seq_n = next_seq_n.fetch_add(1, Ordering::SeqCst);
+                    let seed = sequence_seed(master_seed, curr_seq_n);
+                    let (mut passable_state_new, init, init_seed) = match &initialized {
+                        Some(initialized) => {
+                            let passable_state_new = initialized.passable_state.clone();
+                            passable_state_new
+                                .client()
+                                .restore(&initialized.snapshot)
+                                .await;
+                            (passable_state_new, SequenceInit::Restored, initialized.seed)
+                        }
+                        None => {
+                            let mut validator = create_handler();
+                            let mut passable_state_new = passable_state.clone();
+                            passable_state_new.client = Some(validator.start().await);
+                            let init = if snapshots {
+                                SequenceInit::RunAndCapture
+                            } else {
+                                SequenceInit::Run
+                            };
+                            (passable_state_new, init, seed)
+                        }
+                    };
+                    let history = SequenceHistory::default();
+                    passable_state_new.history = Some(history.clone());
+                    let thread_safe_passed_state = Arc::new(Mutex::new(passable_state_new));
+
+                    let result = tokio::spawn(with_seeded_rng(
+                        seed,
+                        Self::run_sequence(
+                            curr_seq_n,
+                            seed,
+                            n_flows,
+                            thread_safe_passed_state,
+                            context.clone(),
+                            budget.clone(),
+                            history.clone(),
+                            init,
+                        ),
+                    ))
+                    .await;
+
+                    match result {
+                        Ok(Some(initialized_new)) => initialized = Some(initialized_new),
+                        Ok(None) => {}
+                        // Stop at the first failing sequence
+                        Err(error) => {
+                            budget.stop();
+                            failure.lock().unwrap().get_or_insert(SequenceFailure {
+                                curr_seq_n,
+                                seed,
+                                init_seed,
+                                history,
+                                error,
+                            });
+                            break;
+                        }
+                    }
+                    if !budget.is_continuous() || budget.is_exhausted() {
+                        break;
+                    }
+                }
             });
         }
 
         local.await;
-        let clients = clients.lock().await;
-
-        for (i, client) in clients.iter().enumerate() {
-            let mut passable_state_new = self.passable_state.clone();
-            passable_state_new.client = Some(client.clone());
-            let thread_safe_passed_state = Arc::new(Mutex::new(passable_state_new));
-            let init_handlers = self.init_handlers.clone();
-
-            debug!("Running sequence {}/{}", i + 1, n_seq);
-
-            let flows = self.flows.clone();
-            let invariants = self.invariants.clone();
-            let future = tokio::spawn(async move {
-                Self::run_sequence(
-                    i,
-                    n_flows,
-                    thread_safe_passed_state,
-                    flows,
-                    invariants,
-                    init_handlers,
-                )
-                .await;
-            });
-            futures.push(future);
+
+        let report = self.stats.lock().unwrap().report(started_at.elapsed());
+        report.print();
+        if let Some(report_path) = self.report_path() {
+            match report.write_json(&report_path) {
+                Ok(()) => println!("Report written to {}", report_path.display()),
+                Err(e) => println!("Unable to write the report: {e}"),
+            }
         }
-        match select_all(futures).await {
-            (Ok(_), _, _) => {}
-            (Err(e), index, _) => {
-                writer.print(index);
-                panic!("Fuzzing ended: {}", e);
+
+        if let Some(log_dir) = &self.log_dir {
+            match writer.write_to_dir(log_dir) {
+                Ok(()) => println!("Logs of the sequences written to {}", log_dir.display()),
+                Err(e) => println!("Unable to write the logs of the sequences: {e}"),
             }
         }
+
+        let failure = failure.lock().unwrap().take();
+        if let Some(SequenceFailure {
+            curr_seq_n,
+            seed,
+            init_seed,
+            history,
+            error,
+        }) = failure
+        {
+            println!("Log of the failing sequence {curr_seq_n}:");
+            writer.writer(curr_seq_n).print();
+            // A sequence started from a snapshot depends on the init handlers of another sequence
+            if seed == init_seed {
+                println!(
+                    "Sequence {curr_seq_n} failed, replay it with `trdelnik fuzz run <TEST_NAME> --seed {seed} --sequences 1`"
+                );
+            } else {
+                println!("Sequence {curr_seq_n} failed");
+            }
+            let mut sequence = history.lock().unwrap().clone();
+            if self.shrink {
+                sequence = self.shrink_sequence(init_seed, sequence).await;
+            }
+            self.save_to_corpus(seed, init_seed, &sequence).await;
+            panic!("Fuzzing ended: {}", error);
+        }
     }
 }
 
 pub trait Handler<T>: Clone + Send + Sized + 'static {
-    type Future: Future<Output = ()> + Send + 'static;
+    type Future: Future<Output = anyhow::Result<()>> + Send + 'static;
+
+    fn call(self, builder: OwnedMutexGuard<PassableState>) -> Self::Future;
+}
+
+/// A predicate over the same arguments as [Handler], used to enable or disable flows.
+pub trait Guard<T>: Clone + Send + Sized + 'static {
+    type Future: Future<Output = bool> + Send + 'static;
 
     fn call(self, builder: OwnedMutexGuard<PassableState>) -> Self::Future;
 }
 
-trait FromPassable {
-    fn from_passable(builder: &OwnedMutexGuard<PassableState>) -> Self;
+/// Extracts a handler argument from the state of the sequence.
+///
+/// The arguments are extracted in order before the handler runs. Locks are awaited
+/// instead of blocking the thread, so the handlers work on a current-thread runtime too.
+trait FromPassable: Sized {
+    fn from_passable(builder: &PassableState) -> MyBoxFuture<Self>;
 }
 
+/// Exclusive access to the state registered using the `with_state` method.
+///
+/// The state stays locked until the handler finishes, use [StateRef] when the handler
+/// only reads the state. A handler must not take both `State<T>` and `StateRef<T>` of the same `T`.
 #[derive(Debug)]
-pub struct State<T: 'static + Send + CloneAny + Sync + Clone + Debug>(pub OwnedMutexGuard<T>);
+pub struct State<T: 'static + Send + CloneAny + Sync + Clone + Debug>(pub OwnedRwLockWriteGuard<T>);
 
 impl<T: 'static + Send + CloneAny + Sync + Clone + Debug> FromPassable for State<T> {
-    fn from_passable(builder: &OwnedMutexGuard<PassableState>) -> State<T> {
-        let state = builder
-            .state
-            .get::<CustomArcMutex<T>>()
-            .expect("Expected state with this type was not found. Have you registered it using the `with_state` method on builder?");
-
-        let owned_lock = task::block_in_place(move || {
-            Handle::current().block_on(async move { state.clone_arc().lock_owned().await })
-        });
+    fn from_passable(builder: &PassableState) -> MyBoxFuture<State<T>> {
+        let state = shared_state::<T>(builder);
+        Box::pin(async move { State(state.write_owned().await) })
+    }
+}
+
+/// Shared read-only access to the state registered using the `with_state` method.
+///
+/// Unlike [State], it does not take an exclusive lock, so e.g. guards and invariants
+/// reading the same state do not wait for each other.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// async fn is_locked(StateRef(state): StateRef<TurnstileExpectedState>) -> bool {
+///     state.locked
+/// }
+/// ```
+#[derive(Debug)]
+pub struct StateRef<T: 'static + Send + CloneAny + Sync + Clone + Debug>(
+    pub OwnedRwLockReadGuard<T>,
+);
 
-        State(owned_lock)
+impl<T: 'static + Send + CloneAny + Sync + Clone + Debug> FromPassable for StateRef<T> {
+    fn from_passable(builder: &PassableState) -> MyBoxFuture<StateRef<T>> {
+        let state = shared_state::<T>(builder);
+        Box::pin(async move { StateRef(state.read_owned().await) })
     }
 }
 
+/// Returns the crates logged by default, `trdelnik_fuzz` and the fuzz test binary.
+///
+/// The target of the events from the fuzz test starts with its crate name, which is the name
+/// of the binary with dashes replaced by underscores.
+fn default_log_targets() -> Vec<String> {
+    let mut targets = vec![module_path!()
+        .split("::")
+        .next()
+        .expect("Module path is not empty")
+        .to_owned()];
+    if let Some(binary) = std::env::current_exe().ok().and_then(|path| {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().replace('-', "_"))
+    }) {
+        targets.push(binary);
+    }
+    targets
+}
+
+/// Returns `true` when the `target` is one of the `log_targets` or their submodule.
+fn is_log_target(log_targets: &[String], target: &str) -> bool {
+    log_targets.iter().any(|log_target| {
+        target
+            .strip_prefix(log_target.as_str())
+            .map_or(false, |rest| rest.is_empty() || rest.starts_with("::"))
+    })
+}
+
+fn shared_state<T: 'static + Send + CloneAny + Sync + Clone>(
+    builder: &PassableState,
+) -> Arc<RwLock<T>> {
+    builder
+        .state
+        .get::<SharedState<T>>()
+        .expect("Expected state with this type was not found. Have you registered it using the `with_state` method on builder?")
+        .clone_arc()
+}
+
 macro_rules! generate_handler {
     ($( $($arg:ident)* ),+) => (
         $(
             #[allow(unused_parens, non_snake_case)]
-            impl<F, Fut, $($arg),*> Handler<($($arg),*)> for F
+            impl<F, Fut, R, $($arg),*> Handler<($($arg),*)> for F
             where
                 F: FnOnce($($arg),*) -> Fut + Clone + Send + 'static,
-                Fut: Future<Output = ()> + Send + 'static,
-                $( $arg: FromPassable + Debug ),*
+                Fut: Future<Output = R> + Send + 'static,
+                R: IntoFlowResult,
+                $( $arg: FromPassable + Debug + Send + 'static ),*
             {
-                type Future = Pin<Box<dyn Future<Output = ()> + Send>>;
+                type Future = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send>>;
 
                 fn call(self, fuzz_test_builder: OwnedMutexGuard<PassableState>) -> Self::Future {
                     let fn_name = std::any::type_name::<F>();
                     debug!("Running handler: {}", fn_name);
 
-                    $( let $arg = $arg::from_passable(&fuzz_test_builder) );*;
+                    async move {
+                        $( let $arg = $arg::from_passable(&fuzz_test_builder).await; )*
+                        drop(fuzz_test_builder);
 
-                    (self)($($arg),*).boxed()
+                        (self)($($arg),*).await.into_flow_result()
+                    }
+                    .boxed()
+                }
+            }
+
+            #[allow(unused_parens, non_snake_case)]
+            impl<F, Fut, $($arg),*> Guard<($($arg),*)> for F
+            where
+                F: FnOnce($($arg),*) -> Fut + Clone + Send + 'static,
+                Fut: Future<Output = bool> + Send + 'static,
+                $( $arg: FromPassable + Debug + Send + 'static ),*
+            {
+                type Future = Pin<Box<dyn Future<Output = bool> + Send>>;
+
+                fn call(self, fuzz_test_builder: OwnedMutexGuard<PassableState>) -> Self::Future {
+                    async move {
+                        $( let $arg = $arg::from_passable(&fuzz_test_builder).await; )*
+                        drop(fuzz_test_builder);
+
+                        (self)($($arg),*).await
+                    }
+                    .boxed()
                 }
             }
         )+
@@ -366,9 +1212,265 @@ generate_handler!(A B C D E G H);
 generate_handler!(A B C D E G H I);
 generate_handler!(A B C D E G H I J);
 generate_handler!(A B C D E G H I J K);
+generate_handler!(A B C D E G H I J K L);
+generate_handler!(A B C D E G H I J K L M);
+generate_handler!(A B C D E G H I J K L M N);
+generate_handler!(A B C D E G H I J K L M N O);
+generate_handler!(A B C D E G H I J K L M N O P);
+generate_handler!(A B C D E G H I J K L M N O P Q);
+
+/// A flow argument generated by the fuzzer from the seed of the flow.
+///
+/// The generated value is logged with the name of the flow and stored with the failing sequences.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// async fn flow_coin(client: Client, Input(dummy_arg): Input<String>) {
+///     // ...
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Input<T: Arbitrary + Debug>(pub T);
+
+impl<T: Arbitrary + Debug + Send + 'static> FromPassable for Input<T> {
+    fn from_passable(builder: &PassableState) -> MyBoxFuture<Self> {
+        let value = with_rng(T::arbitrary);
+        builder.record_input(format!("{value:?}"));
+        future::ready(Input(value)).boxed()
+    }
+}
 
 impl FromPassable for Client {
-    fn from_passable(builder: &OwnedMutexGuard<PassableState>) -> Self {
-        builder.client()
+    fn from_passable(builder: &PassableState) -> MyBoxFuture<Self> {
+        future::ready(builder.client()).boxed()
+    }
+}
+
+/// A flow argument with a random actor from the pool created by the `with_actors` method.
+///
+/// The chosen actor is logged with the name of the flow and stored with the failing sequences.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// async fn flow_withdraw(client: Client, actor: Actor) -> Result<(), ClientError> {
+///     let client = actor.client(&client);
+///     // Send the instructions signed and paid by the actor
+/// }
+/// ```
+pub struct Actor {
+    /// Index of the actor in the pool.
+    pub index: usize,
+    pub keypair: Keypair,
+}
+
+impl Actor {
+    pub fn pubkey(&self) -> Pubkey {
+        self.keypair.pubkey()
+    }
+
+    /// Creates a client with the actor as the payer.
+    pub fn client(&self, client: &Client) -> Client {
+        client.clone_with_payer(self.keypair.insecure_clone())
+    }
+}
+
+impl Clone for Actor {
+    fn clone(&self) -> Self {
+        Self {
+            index: self.index,
+            keypair: self.keypair.insecure_clone(),
+        }
+    }
+}
+
+impl Debug for Actor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Actor {} ({})", self.index, self.pubkey())
+    }
+}
+
+impl FromPassable for Actor {
+    fn from_passable(builder: &PassableState) -> MyBoxFuture<Self> {
+        let keypairs = &builder.actors.keypairs;
+        if keypairs.is_empty() {
+            panic!("There are no actors, create them using the `with_actors` method.");
+        }
+        let index = with_rng(|rng| rng.gen_range(0..keypairs.len()));
+        let actor = Actor {
+            index,
+            keypair: keypairs[index].insecure_clone(),
+        };
+        builder.record_input(format!("{actor:?}"));
+        future::ready(actor).boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn flow_amount(Input(_amount): Input<u64>) {}
+
+    async fn flow_transfer(Input(_transfer): Input<(Pubkey, Option<u8>)>) {}
+
+    async fn flow_locked(Input(_amount): Input<u8>) {}
+
+    async fn flow_flag(Input(_flag): Input<bool>) {}
+
+    #[derive(Clone, Debug)]
+    struct Locked(bool);
+
+    async fn is_unlocked(State(locked): State<Locked>) -> bool {
+        !locked.0
+    }
+
+    fn fuzz_test_builder() -> FuzzTestBuilder {
+        let mut builder = FuzzTestBuilder::new();
+        builder
+            .add_flow(flow_amount)
+            .add_flow_weighted(flow_transfer, 2);
+        builder
+    }
+
+    /// Runs a sequence without a validator, the flows of the test do not use the client.
+    async fn run_sequence(builder: &FuzzTestBuilder, seed: u64) -> Vec<ExecutedFlow> {
+        run_sequence_with_budget(builder, seed, Arc::new(Budget::default())).await
+    }
+
+    async fn run_sequence_with_budget(
+        builder: &FuzzTestBuilder,
+        seed: u64,
+        budget: Arc<Budget>,
+    ) -> Vec<ExecutedFlow> {
+        let history = SequenceHistory::default();
+        let mut passable_state = builder.passable_state.clone();
+        passable_state.history = Some(history.clone());
+        with_seeded_rng(
+            seed,
+            FuzzTestBuilder::run_sequence(
+                0,
+                seed,
+                20,
+                Arc::new(Mutex::new(passable_state)),
+                builder.context(),
+                budget,
+                history.clone(),
+                SequenceInit::Restored,
+            ),
+        )
+        .await;
+        let executed_flows = history.lock().unwrap().clone();
+        executed_flows
+    }
+
+    #[test]
+    fn test_log_targets() {
+        let log_targets = vec!["trdelnik_fuzz".to_owned(), "fuzz_test".to_owned()];
+        assert!(is_log_target(&log_targets, "trdelnik_fuzz"));
+        assert!(is_log_target(&log_targets, "trdelnik_fuzz::builder"));
+        assert!(is_log_target(&log_targets, "fuzz_test::flows"));
+        assert!(!is_log_target(&log_targets, "fuzz_tests"));
+        assert!(!is_log_target(&log_targets, "solana_runtime::bank"));
+
+        assert_eq!(default_log_targets()[0], "trdelnik_fuzz");
+    }
+
+    #[tokio::test]
+    async fn test_same_seed_reproduces_sequence() {
+        let builder = fuzz_test_builder();
+        let sequence = run_sequence(&builder, 42).await;
+        assert_eq!(sequence.len(), 20);
+        assert!(sequence
+            .iter()
+            .all(|executed_flow| executed_flow.inputs.len() == 1));
+
+        assert_eq!(run_sequence(&fuzz_test_builder(), 42).await, sequence);
+        assert_ne!(run_sequence(&builder, 43).await, sequence);
+    }
+
+    #[tokio::test]
+    async fn test_guards_and_weights() {
+        let mut builder = FuzzTestBuilder::new();
+        builder
+            .with_state(Locked(true))
+            .add_flow(flow_amount)
+            .add_flow_weighted(flow_transfer, 9)
+            .add_guarded_flow(flow_locked, 100, is_unlocked)
+            .add_flow_weighted(flow_flag, 0);
+
+        let mut executions = [0; 4];
+        for seed in 0..10 {
+            for executed_flow in run_sequence(&builder, seed).await {
+                executions[executed_flow.flow] += 1;
+            }
+        }
+        assert_eq!(executions[0] + executions[1], 200);
+        assert!(executions[1] > 3 * executions[0]);
+        // Neither the guarded-off flow nor the zero-weight flow is ever selected
+        assert_eq!(executions[2..], [0, 0]);
+
+        let stats = builder.stats.lock().unwrap();
+        assert_eq!(stats.flows[2].disabled, 200);
+        assert_eq!(stats.flows[3].disabled, 200);
+    }
+
+    #[derive(Clone, Debug)]
+    struct Counter(u64);
+
+    #[derive(Clone, Debug, Default)]
+    struct Observed(Arc<std::sync::Mutex<Vec<u64>>>);
+
+    async fn flow_increment(State(mut counter): State<Counter>) {
+        counter.0 += 1;
+    }
+
+    async fn observe_counter(
+        StateRef(counter): StateRef<Counter>,
+        StateRef(observed): StateRef<Observed>,
+    ) {
+        observed.0.lock().unwrap().push(counter.0);
+    }
+
+    #[tokio::test]
+    async fn test_invariant_reads_state_mutated_by_flow() {
+        let observed = Observed::default();
+        let mut builder = FuzzTestBuilder::new();
+        builder
+            .with_state(Counter(0))
+            .with_state(observed.clone())
+            .add_flow(flow_increment)
+            .add_invariant(observe_counter);
+
+        run_sequence(&builder, 42).await;
+        // The invariant runs after every flow and sees all the previous increments
+        assert_eq!(*observed.0.lock().unwrap(), (1..=20).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn test_iteration_budget_is_shared() {
+        let builder = fuzz_test_builder();
+        let budget = Arc::new(Budget::new(None, Some(30)));
+        let first = run_sequence_with_budget(&builder, 1, budget.clone()).await;
+        let second = run_sequence_with_budget(&builder, 2, budget.clone()).await;
+        assert_eq!((first.len(), second.len()), (20, 10));
+        assert!(budget.is_exhausted());
+        assert!(run_sequence_with_budget(&builder, 3, budget)
+            .await
+            .is_empty());
+
+        let stats = builder.stats.lock().unwrap();
+        assert_eq!(stats.sequences, 3);
+        let executions = stats.flows.iter().map(|flow| flow.executions).sum::<u64>();
+        assert_eq!(executions, 30);
+        for (i, flow) in stats.flows.iter().enumerate() {
+            let executed = first
+                .iter()
+                .chain(second.iter())
+                .filter(|executed_flow| executed_flow.flow == i)
+                .count();
+            assert_eq!(flow.executions, executed as u64);
+        }
     }
 }
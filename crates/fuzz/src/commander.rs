@@ -1,8 +1,18 @@
-use std::env;
+use std::{
+    env,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use fehler::throw;
 use thiserror::Error;
 use tokio::process::Command;
+use trdelnik_client::{anyhow, fuzz_test_generator};
+
+use crate::budget::{DURATION_ENV_VAR, ITERATIONS_ENV_VAR, SEQUENCES_ENV_VAR};
+use crate::corpus::{CORPUS_ENV_VAR, REPLAY_ENV_VAR};
+use crate::random::SEED_ENV_VAR;
+use crate::stats::REPORT_ENV_VAR;
 use tokio::{
     fs::{self, OpenOptions},
     io::AsyncWriteExt,
@@ -12,68 +22,165 @@ use tokio::{
 pub enum Error {
     #[error("Run this command in the root of the workspace")]
     BadWorkspace,
+    #[error("The fuzz test found a failing sequence")]
+    FuzzTestFailed,
+    #[error("The replay of the stored cases failed")]
+    ReplayFailed,
+    #[error("Honggfuzz found a crash")]
+    HonggfuzzFailed,
 }
 
+/// Version of `honggfuzz` added to the dependencies of `trdelnik-tests` with the first honggfuzz target.
+const HONGGFUZZ_VERSION: &str = "0.5.55";
+
 #[derive(Default)]
 pub struct Commander {}
 
+/// Options of `trdelnik fuzz run`, they override the settings of the `FuzzTestBuilder`.
+#[derive(Default)]
+pub struct FuzzRunOptions {
+    pub seed: Option<u64>,
+    pub sequences: Option<usize>,
+    pub duration: Option<Duration>,
+    pub iterations: Option<u64>,
+    pub report: Option<PathBuf>,
+}
+
 impl Commander {
     pub async fn new_fuzz_test(&self, name: String) -> Result<(), Error> {
+        let name = name.trim_end_matches(".rs").to_owned();
+        let test_content = match Self::generate_fuzz_test().await {
+            Ok(test_content) => test_content,
+            Err(e) => {
+                println!("Unable to generate the fuzz test from the programs, using the default template: {e}");
+                include_str!("templates/test.rs").to_owned()
+            }
+        };
+        Self::add_fuzz_target(&name, "fuzz-tests", &test_content).await
+    }
+
+    /// Creates a honggfuzz target driving `program_test::run_fuzz_iteration`, which is run
+    /// by `trdelnik fuzz honggfuzz`. `honggfuzz` is added to the dependencies of `trdelnik-tests`.
+    pub async fn new_honggfuzz_test(&self, name: String) -> Result<(), Error> {
+        let name = name.trim_end_matches(".rs").to_owned();
+        Self::add_fuzz_target(&name, "hfuzz-tests", include_str!("templates/hfuzz.rs")).await?;
+
+        let cargo_toml_path = Path::new("trdelnik-tests").join("Cargo.toml");
+        let cargo_toml = fs::read_to_string(&cargo_toml_path)
+            .await
+            .expect("Unable to read Cargo.toml");
+        if !cargo_toml.contains("honggfuzz") {
+            Self::append_to_cargo_toml(&format!(
+                "
+[dependencies.honggfuzz]
+version = \"{HONGGFUZZ_VERSION}\"
+"
+            ))
+            .await;
+        }
+        if !cargo_toml.contains("in-process") {
+            println!(
+                "Enable the `in-process` feature of `trdelnik-fuzz` in trdelnik-tests/Cargo.toml"
+            );
+        }
+        Ok(())
+    }
+
+    /// Writes the fuzz test into the `folder` of `trdelnik-tests` and adds its binary target.
+    async fn add_fuzz_target(name: &str, folder: &str, content: &str) -> Result<(), Error> {
         let root_path = env::current_dir().expect("Unable to get current directory");
         let trdelnik_test_folder = root_path.join("trdelnik-tests");
         if !trdelnik_test_folder.exists() {
             throw!(Error::BadWorkspace)
         }
-        let fuzz_test_folder = trdelnik_test_folder.join("fuzz-tests");
+        let fuzz_test_folder = trdelnik_test_folder.join(folder);
         if !fuzz_test_folder.exists() {
             fs::create_dir(fuzz_test_folder.clone())
                 .await
-                .expect("Unable to create fuzz-tests folder");
+                .unwrap_or_else(|_| panic!("Unable to create {folder} folder"));
         }
-        let name = if name.ends_with(".rs") {
-            name
-        } else {
-            format!("{}.rs", name)
-        };
-        let test_path = fuzz_test_folder.join(name.clone());
+        let file_name = format!("{name}.rs");
+        let test_path = fuzz_test_folder.join(&file_name);
         if test_path.exists() {
             panic!("Fuzz test with name {} already exists", name);
         }
-        let test_content = include_str!("templates/test.rs");
-        fs::write(test_path.clone(), &test_content)
+        fs::write(test_path.clone(), content)
             .await
-            .unwrap_or_else(|_| panic!("Unable to create fuzz test in path {}", test_path.display()));
+            .unwrap_or_else(|_| {
+                panic!("Unable to create fuzz test in path {}", test_path.display())
+            });
 
-        OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open(trdelnik_test_folder.join("Cargo.toml"))
-            .await
-            .expect("Unable to open Cargo.toml")
-            .write_all(
-                format!(
-                    "
+        Self::append_to_cargo_toml(&format!(
+            "
 [[bin]]
 name = \"{name}\"
-path = \"fuzz-tests/{name}\"
+path = \"{folder}/{file_name}\"
 test = false
 doc = false
             "
-                )
-                .as_bytes(),
-            )
+        ))
+        .await;
+
+        Ok(())
+    }
+
+    async fn append_to_cargo_toml(content: &str) {
+        OpenOptions::new()
+            .write(true)
+            .append(true)
+            .open(Path::new("trdelnik-tests").join("Cargo.toml"))
+            .await
+            .expect("Unable to open Cargo.toml")
+            .write_all(content.as_bytes())
             .await
             .expect("Could not add fuzz test to Cargo.toml");
+    }
 
-        Ok(())
+    /// Generates the fuzz test skeleton with one flow per instruction of the workspace programs.
+    async fn generate_fuzz_test() -> anyhow::Result<String> {
+        let idl = trdelnik_client::Commander::with_root(".")
+            .program_idl()
+            .await?;
+        let code = fuzz_test_generator::generate_source_code(idl);
+        Ok(trdelnik_client::Commander::format_program_code(&code).await?)
     }
 
-    pub async fn run_fuzz_test(&self, name: String) -> Result<(), Error> {
-        let success = Command::new("cargo")
+    fn fuzz_test_command(name: &str) -> Command {
+        let mut command = Command::new("cargo");
+        command
             .current_dir("trdelnik-tests")
+            .env(CORPUS_ENV_VAR, format!("fuzz-tests/{name}/corpus"))
             .arg("run")
             .arg("--bin")
-            .arg(name)
+            .arg(name);
+        command
+    }
+
+    pub async fn run_fuzz_test(&self, name: String, options: FuzzRunOptions) -> Result<(), Error> {
+        let mut command = Self::fuzz_test_command(&name);
+        if let Some(seed) = options.seed {
+            command.env(SEED_ENV_VAR, seed.to_string());
+        }
+        if let Some(sequences) = options.sequences {
+            command.env(SEQUENCES_ENV_VAR, sequences.to_string());
+        }
+        if let Some(duration) = options.duration {
+            command.env(
+                DURATION_ENV_VAR,
+                humantime::format_duration(duration).to_string(),
+            );
+        }
+        if let Some(iterations) = options.iterations {
+            command.env(ITERATIONS_ENV_VAR, iterations.to_string());
+        }
+        if let Some(report) = options.report {
+            // The fuzz test runs in the `trdelnik-tests` directory
+            let report = env::current_dir()
+                .expect("Unable to get current directory")
+                .join(report);
+            command.env(REPORT_ENV_VAR, report);
+        }
+        let success = command
             .spawn()
             .expect("Unable to run fuzz test")
             .wait()
@@ -81,7 +188,42 @@ doc = false
             .expect("Unable to start fuzz test")
             .success();
         if !success {
-            println!("Fuzzing ended");
+            throw!(Error::FuzzTestFailed)
+        }
+        Ok(())
+    }
+
+    pub async fn replay_fuzz_test(&self, name: String, case: Option<String>) -> Result<(), Error> {
+        let success = Self::fuzz_test_command(&name)
+            .env(REPLAY_ENV_VAR, case.unwrap_or_default())
+            .spawn()
+            .expect("Unable to replay fuzz test")
+            .wait()
+            .await
+            .expect("Unable to start fuzz test replay")
+            .success();
+        if !success {
+            throw!(Error::ReplayFailed)
+        }
+        Ok(())
+    }
+
+    /// Runs the fuzz test with the honggfuzz coverage-guided engine, the fuzz test needs to use
+    /// the `honggfuzz::fuzz!` macro, see [new_honggfuzz_test](Self::new_honggfuzz_test).
+    pub async fn run_honggfuzz(&self, name: String) -> Result<(), Error> {
+        let success = Command::new("cargo")
+            .current_dir("trdelnik-tests")
+            .arg("hfuzz")
+            .arg("run")
+            .arg(name)
+            .spawn()
+            .expect("Unable to run honggfuzz, install it with `cargo install honggfuzz`")
+            .wait()
+            .await
+            .expect("Unable to start honggfuzz")
+            .success();
+        if !success {
+            throw!(Error::HonggfuzzFailed)
         }
         Ok(())
     }
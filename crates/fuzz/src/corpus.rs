@@ -0,0 +1,159 @@
+use std::{
+    collections::hash_map::DefaultHasher,
+    env,
+    hash::{Hash, Hasher},
+    io,
+    path::PathBuf,
+};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// Environment variable with the path to the corpus directory of the fuzz test,
+/// it is set by the `trdelnik fuzz run` and `trdelnik fuzz replay` commands.
+pub const CORPUS_ENV_VAR: &str = "TRDELNIK_FUZZ_CORPUS";
+
+/// Environment variable instructing the fuzz test to replay the stored cases instead of fuzzing,
+/// it contains the name of the case to replay or is empty to replay all of them.
+pub const REPLAY_ENV_VAR: &str = "TRDELNIK_FUZZ_REPLAY";
+
+/// A stored sequence of flows which can be re-executed.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CorpusCase {
+    /// Seed of the sequence, it is used to generate the random inputs of the init handlers.
+    pub seed: u64,
+    pub flows: Vec<CorpusFlow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CorpusFlow {
+    pub name: String,
+    pub seed: u64,
+    /// Inputs generated for the flow, they are regenerated from the seed during the replay.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inputs: Vec<String>,
+}
+
+impl CorpusCase {
+    /// Name of the case found by the sequence with the given seed, e.g. `42-5f3a9c0b1d2e4f67`.
+    ///
+    /// The hash of the flows distinguishes the cases of the sequences sharing the seed,
+    /// e.g. the sequences started from a snapshot or the runs with a fixed seed.
+    pub fn name(&self, sequence_seed: u64) -> String {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        format!("{sequence_seed}-{:016x}", hasher.finish())
+    }
+}
+
+/// The directory with the stored cases of a fuzz test, i.e. `trdelnik-tests/fuzz-tests/<name>/corpus`.
+pub struct Corpus {
+    dir: PathBuf,
+}
+
+impl Corpus {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Uses the directory from the `TRDELNIK_FUZZ_CORPUS` variable
+    /// or `fuzz-tests/<name of the fuzz test binary>/corpus` if the variable is not set.
+    pub fn from_env() -> Self {
+        match env::var(CORPUS_ENV_VAR) {
+            Ok(dir) => Self::new(dir),
+            Err(_) => {
+                let exe = env::current_exe().expect("Unable to get the fuzz test executable");
+                let name = exe
+                    .file_stem()
+                    .expect("Unable to get the name of the fuzz test executable");
+                Self::new(PathBuf::from("fuzz-tests").join(name).join("corpus"))
+            }
+        }
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// Stores the case as `<case_name>.json` and returns its path.
+    pub async fn save(&self, case_name: &str, case: &CorpusCase) -> io::Result<PathBuf> {
+        fs::create_dir_all(&self.dir).await?;
+        let path = self.dir.join(format!("{case_name}.json"));
+        let content = serde_json::to_string_pretty(case)?;
+        fs::write(&path, content).await?;
+        Ok(path)
+    }
+
+    /// Loads the case with the given name or all the stored cases sorted by name.
+    pub async fn load(&self, case_name: Option<&str>) -> io::Result<Vec<(String, CorpusCase)>> {
+        let mut names = match case_name {
+            Some(case_name) => vec![case_name.trim_end_matches(".json").to_owned()],
+            None => {
+                let mut names = vec![];
+                let mut entries = fs::read_dir(&self.dir).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    let path = entry.path();
+                    if path.extension().map_or(false, |ext| ext == "json") {
+                        if let Some(stem) = path.file_stem() {
+                            names.push(stem.to_string_lossy().into_owned());
+                        }
+                    }
+                }
+                names
+            }
+        };
+        names.sort();
+
+        let mut cases = vec![];
+        for name in names {
+            let content = fs::read_to_string(self.dir.join(format!("{name}.json"))).await?;
+            let case = serde_json::from_str(&content)?;
+            cases.push((name, case));
+        }
+        Ok(cases)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(flow_seed: u64) -> CorpusCase {
+        CorpusCase {
+            seed: 42,
+            flows: vec![
+                CorpusFlow {
+                    name: "fuzz_test::flow_coin".to_owned(),
+                    seed: flow_seed,
+                    inputs: vec!["100".to_owned()],
+                },
+                CorpusFlow {
+                    name: "fuzz_test::flow_push".to_owned(),
+                    seed: 7,
+                    inputs: vec![],
+                },
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_save_and_load() {
+        let dir = env::temp_dir().join(format!("trdelnik-corpus-{}", std::process::id()));
+        let corpus = Corpus::new(&dir);
+        let first = case(1);
+        let second = case(2);
+        assert_eq!(first.name(42), case(1).name(42));
+        assert_ne!(first.name(42), second.name(42));
+
+        corpus.save(&first.name(42), &first).await.unwrap();
+        corpus.save(&second.name(42), &second).await.unwrap();
+        let loaded = corpus.load(None).await.unwrap();
+        let loaded_one = corpus.load(Some(&first.name(42))).await.unwrap();
+        fs::remove_dir_all(&dir).await.unwrap();
+
+        let mut expected = vec![(first.name(42), first), (second.name(42), second)];
+        expected.sort_by(|a, b| a.0.cmp(&b.0));
+        assert_eq!(loaded, expected);
+        assert_eq!(loaded_one, expected[..1].to_vec());
+    }
+}
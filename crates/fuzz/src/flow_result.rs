@@ -0,0 +1,136 @@
+use trdelnik_client::{
+    anyhow, solana_sdk::program_error::ProgramError, AnchorErrorInfo, ClientError,
+};
+
+/// The return type of flows, invariants and init handlers.
+///
+/// Handlers can return `()` or any `Result<(), E>` where `E` converts into [anyhow::Error],
+/// e.g. `Result<(), ClientError>` returned by the [Client](trdelnik_client::Client) methods.
+/// Errors returned by flows are classified as expected rejections or unexpected errors,
+/// errors of invariants and init handlers always fail the sequence.
+pub trait IntoFlowResult {
+    fn into_flow_result(self) -> anyhow::Result<()>;
+}
+
+impl IntoFlowResult for () {
+    fn into_flow_result(self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+impl<E: Into<anyhow::Error>> IntoFlowResult for Result<(), E> {
+    fn into_flow_result(self) -> anyhow::Result<()> {
+        self.map_err(Into::into)
+    }
+}
+
+/// How the builder treats an error returned by a flow.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum FlowError {
+    /// The instruction was rejected by the program with an error code, e.g. because of a violated
+    /// constraint. It is counted by its error code and the run continues.
+    Rejected(String),
+    /// Any other error, e.g. a panic of the program, an exceeded compute budget, a failed
+    /// RPC request or an error code the flow does not expect. It fails the sequence.
+    Unexpected,
+}
+
+/// Classifies the error returned by a flow.
+///
+/// Only the program errors with a code, i.e. `InstructionError::Custom` and Anchor errors, are rejections.
+/// Anchor errors are named from the transaction logs, e.g. `ConstraintMut (2000)`, other codes
+/// by their number, e.g. `Custom(1)`. When the flow declares the `expected_codes`,
+/// the other codes are unexpected.
+pub(crate) fn classify_error(error: &anyhow::Error, expected_codes: Option<&[u32]>) -> FlowError {
+    for cause in error.chain() {
+        let rejection = match cause.downcast_ref::<ClientError>() {
+            Some(ClientError::ProgramError(ProgramError::Custom(code))) => {
+                Some((*code, format!("Custom({code})")))
+            }
+            Some(error) => AnchorErrorInfo::from_client_error(error).map(|info| {
+                let name = match info.name {
+                    Some(name) => format!("{name} ({})", info.code),
+                    None => format!("Custom({})", info.code),
+                };
+                (info.code, name)
+            }),
+            None => None,
+        };
+        if let Some((code, name)) = rejection {
+            return match expected_codes {
+                Some(expected_codes) if !expected_codes.contains(&code) => FlowError::Unexpected,
+                _ => FlowError::Rejected(name),
+            };
+        }
+    }
+    FlowError::Unexpected
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trdelnik_client::{
+        anchor_lang::error::{AnchorError, Error},
+        solana_sdk::{instruction::InstructionError, transaction::TransactionError},
+    };
+
+    fn transaction_error(error: TransactionError) -> anyhow::Error {
+        ClientError::SolanaClientError(error.into()).into()
+    }
+
+    #[test]
+    fn test_classify_error() {
+        let custom = transaction_error(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(6000),
+        ));
+        assert_eq!(
+            classify_error(&custom, None),
+            FlowError::Rejected("Custom(6000)".to_owned())
+        );
+        assert_eq!(
+            classify_error(&custom.context("coin failed"), Some(&[6000, 6001])),
+            FlowError::Rejected("Custom(6000)".to_owned())
+        );
+        let custom = transaction_error(TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(6000),
+        ));
+        assert_eq!(
+            classify_error(&custom, Some(&[6001])),
+            FlowError::Unexpected
+        );
+
+        let anchor_error = ClientError::AnchorError(Error::AnchorError(AnchorError {
+            error_name: "ConstraintMut".to_owned(),
+            error_code_number: 2000,
+            error_msg: "A mut constraint was violated".to_owned(),
+            error_origin: None,
+            compared_values: None,
+        }));
+        assert_eq!(
+            classify_error(&anchor_error.into(), None),
+            FlowError::Rejected("ConstraintMut (2000)".to_owned())
+        );
+
+        let unexpected = [
+            transaction_error(TransactionError::InstructionError(
+                0,
+                InstructionError::ProgramFailedToComplete,
+            )),
+            transaction_error(TransactionError::InstructionError(
+                1,
+                InstructionError::ComputationalBudgetExceeded,
+            )),
+            transaction_error(TransactionError::InstructionError(
+                0,
+                InstructionError::AccountAlreadyInitialized,
+            )),
+            transaction_error(TransactionError::InsufficientFundsForFee),
+            anyhow::anyhow!("connection refused"),
+        ];
+        for error in unexpected {
+            assert_eq!(classify_error(&error, None), FlowError::Unexpected);
+        }
+    }
+}
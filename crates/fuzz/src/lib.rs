@@ -8,3 +8,26 @@ pub use commander::*;
 
 mod random;
 pub use random::*;
+
+mod arbitrary;
+pub use arbitrary::*;
+pub use rand;
+pub use trdelnik_test::Arbitrary;
+
+mod shrink;
+
+mod budget;
+mod flow_result;
+mod stats;
+pub use flow_result::IntoFlowResult;
+
+mod model;
+pub use model::{diff_fields, FieldDiff, Model};
+
+mod substitution;
+pub use substitution::{AccountSubstitution, Substitution, SubstitutionFinding};
+
+pub mod corpus;
+
+#[cfg(feature = "in-process")]
+pub mod program_test;
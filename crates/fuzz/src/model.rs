@@ -0,0 +1,224 @@
+use std::fmt::{self, Debug};
+
+use trdelnik_client::{anchor_lang::AccountDeserialize, anyhow, Client, Pubkey};
+
+use crate::builder::StateRef;
+
+/// A model of on-chain accounts which is compared with the watched accounts after every flow.
+///
+/// The model is registered using the `with_model` method of the builder and updated by flows
+/// through `State<M>`, the same way as any other state.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[derive(Clone, Debug)]
+/// struct TurnstileModel {
+///     state: Pubkey,
+///     locked: bool,
+///     res: bool,
+/// }
+///
+/// impl Model for TurnstileModel {
+///     type Account = turnstile::State;
+///
+///     fn expected_accounts(&self) -> Vec<(Pubkey, turnstile::State)> {
+///         vec![(self.state, turnstile::State { locked: self.locked, res: self.res })]
+///     }
+/// }
+/// ```
+pub trait Model: Clone + Debug + Send + Sync + 'static {
+    /// The Anchor account type of the watched accounts.
+    type Account: AccountDeserialize + Debug + Send + 'static;
+
+    /// Returns the watched accounts with their content expected by the model.
+    fn expected_accounts(&self) -> Vec<(Pubkey, Self::Account)>;
+}
+
+/// A field with different values in the model and on-chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    /// Path of the field, e.g. `config.authority` or `items[2]`.
+    pub path: String,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for FieldDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let missing = "<missing>".to_owned();
+        write!(
+            f,
+            "{}: model {}, on-chain {}",
+            self.path,
+            self.expected.as_ref().unwrap_or(&missing),
+            self.actual.as_ref().unwrap_or(&missing)
+        )
+    }
+}
+
+/// Compares the values field by field using their pretty [Debug] representations.
+pub fn diff_fields(expected: &impl Debug, actual: &impl Debug) -> Vec<FieldDiff> {
+    let expected_fields = debug_fields(expected);
+    let actual_fields = debug_fields(actual);
+
+    let mut diffs = vec![];
+    for (path, expected_value) in expected_fields.iter() {
+        let actual_value = actual_fields
+            .iter()
+            .find(|(actual_path, _)| actual_path == path)
+            .map(|(_, value)| value);
+        if actual_value != Some(expected_value) {
+            diffs.push(FieldDiff {
+                path: path.clone(),
+                expected: Some(expected_value.clone()),
+                actual: actual_value.cloned(),
+            });
+        }
+    }
+    for (path, actual_value) in actual_fields.iter() {
+        if !expected_fields
+            .iter()
+            .any(|(expected_path, _)| expected_path == path)
+        {
+            diffs.push(FieldDiff {
+                path: path.clone(),
+                expected: None,
+                actual: Some(actual_value.clone()),
+            });
+        }
+    }
+    diffs
+}
+
+/// Flattens the pretty [Debug] representation into the paths and values of the leaf fields.
+fn debug_fields(value: &impl Debug) -> Vec<(String, String)> {
+    let repr = format!("{value:#?}");
+    let mut lines = repr.lines();
+    let root = lines.next().unwrap_or_default();
+    if !root.ends_with(['{', '[', '(']) {
+        return vec![(String::new(), root.to_owned())];
+    }
+
+    let mut path: Vec<String> = vec![];
+    // Index of the next element on every nesting level, used for sequences and tuples
+    let mut indices = vec![0];
+    let mut fields = vec![];
+    for line in lines {
+        let line = line.trim().trim_end_matches(',');
+        if matches!(line, "}" | "]" | ")") {
+            path.pop();
+            indices.pop();
+            continue;
+        }
+        let (name, value) = match line.split_once(": ") {
+            Some((name, value)) if is_field_name(name) => (name.to_owned(), value),
+            _ => {
+                let index = indices.last_mut().expect("Unbalanced Debug representation");
+                *index += 1;
+                (format!("[{}]", *index - 1), line)
+            }
+        };
+        let field_path = match path.last() {
+            Some(parent) if name.starts_with('[') => format!("{parent}{name}"),
+            Some(parent) => format!("{parent}.{name}"),
+            None => name,
+        };
+        if value.ends_with(['{', '[', '(']) {
+            path.push(field_path);
+            indices.push(0);
+        } else {
+            fields.push((field_path, value.to_owned()));
+        }
+    }
+    fields
+}
+
+fn is_field_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+/// The invariant registered for every model, it fails when any watched account diverges.
+pub(crate) async fn check_model<M: Model>(
+    client: Client,
+    StateRef(model): StateRef<M>,
+) -> anyhow::Result<()> {
+    let mut report = vec![];
+    for (pubkey, expected) in model.expected_accounts() {
+        let actual = match client.account_data::<M::Account>(pubkey).await {
+            Ok(actual) => actual,
+            Err(e) => {
+                report.push(format!("  account {pubkey}: unable to fetch it: {e}"));
+                continue;
+            }
+        };
+        let diffs = diff_fields(&expected, &actual);
+        if !diffs.is_empty() {
+            report.push(format!("  account {pubkey}:"));
+            report.extend(diffs.iter().map(|diff| format!("    {diff}")));
+        }
+    }
+    if !report.is_empty() {
+        anyhow::bail!(
+            "The model {} diverged from the on-chain state:\n{}",
+            std::any::type_name::<M>(),
+            report.join("\n")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The fields are read only through the `Debug` output
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct Inner {
+        amount: u64,
+    }
+
+    #[allow(dead_code)]
+    #[derive(Debug)]
+    struct Account {
+        locked: bool,
+        inner: Inner,
+        items: Vec<u8>,
+    }
+
+    #[test]
+    fn test_diff_fields() {
+        let expected = Account {
+            locked: true,
+            inner: Inner { amount: 10 },
+            items: vec![1, 2],
+        };
+        let actual = Account {
+            locked: false,
+            inner: Inner { amount: 10 },
+            items: vec![1, 3, 4],
+        };
+        assert_eq!(
+            diff_fields(&expected, &actual),
+            vec![
+                FieldDiff {
+                    path: "locked".to_owned(),
+                    expected: Some("true".to_owned()),
+                    actual: Some("false".to_owned()),
+                },
+                FieldDiff {
+                    path: "items[1]".to_owned(),
+                    expected: Some("2".to_owned()),
+                    actual: Some("3".to_owned()),
+                },
+                FieldDiff {
+                    path: "items[2]".to_owned(),
+                    expected: None,
+                    actual: Some("4".to_owned()),
+                },
+            ]
+        );
+        assert!(diff_fields(&expected, &expected).is_empty());
+    }
+}
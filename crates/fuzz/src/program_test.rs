@@ -0,0 +1,278 @@
+//! In-process execution backend for coverage-guided fuzzing.
+//!
+//! Instead of sending transactions to a [Validator](trdelnik_client::Validator) over RPC,
+//! the instructions are processed by a bank running in the fuzz test process. When the program
+//! is added with its native entrypoint (see [processor]), the program code is compiled into
+//! the fuzz test binary and instrumented by the fuzzing engine (e.g. honggfuzz),
+//! so the engine receives the coverage feedback from the program.
+//!
+//! The backend is separate from [FuzzTestBuilder](crate::FuzzTestBuilder), whose flows receive
+//! the RPC [Client](trdelnik_client::Client). Fuzz iterations are written against [ProgramTestClient].
+
+use std::future::Future;
+
+use fehler::{throw, throws};
+use solana_program_test::{BanksClientError, ProgramTestContext};
+use thiserror::Error;
+use trdelnik_client::{
+    anchor_lang::{AccountDeserialize, InstructionData, ToAccountMetas},
+    solana_sdk::{account::Account, transaction::Transaction},
+    Instruction, Keypair, Pubkey, Signer,
+};
+
+use crate::random::with_input_rng;
+
+pub use solana_program_test::{self, processor, ProgramTest};
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("{0:?}")]
+    BanksClient(#[from] BanksClientError),
+    #[error("account {0} not found")]
+    AccountNotFound(Pubkey),
+    #[error("{0:?}")]
+    AccountDeserialize(#[from] trdelnik_client::anchor_lang::error::Error),
+}
+
+/// `ProgramTestClient` processes instructions in-process with an API similar to
+/// [Client](trdelnik_client::Client).
+///
+/// # Example
+///
+/// ```rust,ignore
+/// let mut program_test = ProgramTest::default();
+/// program_test.add_program("turnstile", turnstile::id(), processor!(turnstile::entry));
+/// let mut client = ProgramTestClient::new(program_test).await;
+/// ```
+pub struct ProgramTestClient {
+    context: ProgramTestContext,
+}
+
+impl ProgramTestClient {
+    /// Starts the bank with the programs and accounts added to the `program_test`.
+    pub async fn new(program_test: ProgramTest) -> Self {
+        Self {
+            context: program_test.start_with_context().await,
+        }
+    }
+
+    /// Gets the funded payer of the transactions.
+    pub fn payer(&self) -> &Keypair {
+        &self.context.payer
+    }
+
+    /// Gets the underlying context, e.g. to set accounts or warp to a slot.
+    pub fn context(&mut self) -> &mut ProgramTestContext {
+        &mut self.context
+    }
+
+    /// Processes the Anchor instruction with associated accounts and signers.
+    #[throws]
+    pub async fn send_instruction(
+        &mut self,
+        program: Pubkey,
+        instruction: impl InstructionData,
+        accounts: impl ToAccountMetas,
+        signers: impl IntoIterator<Item = Keypair>,
+    ) {
+        let instruction = Instruction {
+            program_id: program,
+            data: instruction.data(),
+            accounts: accounts.to_account_metas(None),
+        };
+        let signers = signers.into_iter().collect::<Vec<_>>();
+        self.send_transaction(&[instruction], signers.iter())
+            .await?
+    }
+
+    /// Processes the transaction with associated instructions and signers.
+    #[throws]
+    pub async fn send_transaction(
+        &mut self,
+        instructions: &[Instruction],
+        signers: impl IntoIterator<Item = &Keypair>,
+    ) {
+        let mut signers = signers.into_iter().collect::<Vec<_>>();
+        signers.push(&self.context.payer);
+        let blockhash = self.context.banks_client.get_latest_blockhash().await?;
+        let tx = Transaction::new_signed_with_payer(
+            instructions,
+            Some(&self.context.payer.pubkey()),
+            &signers,
+            blockhash,
+        );
+        self.context.banks_client.process_transaction(tx).await?
+    }
+
+    /// Returns all information associated with the account of the provided [Pubkey].
+    #[throws]
+    pub async fn get_account(&mut self, account: Pubkey) -> Option<Account> {
+        self.context.banks_client.get_account(account).await?
+    }
+
+    /// Gets deserialized data from the chosen account serialized with Anchor.
+    #[throws]
+    pub async fn account_data<T: AccountDeserialize>(&mut self, account: Pubkey) -> T {
+        match self.get_account(account).await? {
+            Some(account) => T::try_deserialize(&mut &account.data[..])?,
+            None => throw!(Error::AccountNotFound(account)),
+        }
+    }
+}
+
+/// Executes one iteration of a coverage-guided fuzzing engine.
+///
+/// All the `random_*` helpers and [random_value](crate::random_value) used inside of the future
+/// draw from the engine's `data`, so the engine mutates instruction data and account
+/// choices with the coverage feedback.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use honggfuzz::fuzz;
+///
+/// fn main() {
+///     let runtime = tokio::runtime::Runtime::new().unwrap();
+///     loop {
+///         fuzz!(|data: &[u8]| {
+///             run_fuzz_iteration(&runtime, data, async {
+///                 let mut client = ProgramTestClient::new(program_test()).await;
+///                 // Process the instructions with inputs generated by `random_value` and `random_*` helpers
+///             });
+///         });
+///     }
+/// }
+/// ```
+pub fn run_fuzz_iteration<F: Future>(
+    runtime: &tokio::runtime::Runtime,
+    data: &[u8],
+    f: F,
+) -> F::Output {
+    runtime.block_on(with_input_rng(data, f))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::random_value;
+    use std::io::{self, Write};
+    use trdelnik_client::{
+        anchor_lang::{AnchorSerialize, Discriminator},
+        solana_sdk::{
+            account_info::AccountInfo, entrypoint::ProgramResult, instruction::AccountMeta,
+            program_error::ProgramError,
+        },
+    };
+
+    const PROGRAM_ID: Pubkey = Pubkey::new_from_array([7; 32]);
+    const STORAGE: Pubkey = Pubkey::new_from_array([8; 32]);
+
+    /// Stores the value into the storage account, the owner of the storage has to sign.
+    struct Store {
+        value: u8,
+    }
+
+    impl Discriminator for Store {
+        const DISCRIMINATOR: [u8; 8] = [1; 8];
+    }
+
+    impl AnchorSerialize for Store {
+        fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+            writer.write_all(&[self.value])
+        }
+    }
+
+    impl InstructionData for Store {}
+
+    struct StoreAccounts {
+        storage: Pubkey,
+        owner: Pubkey,
+    }
+
+    impl ToAccountMetas for StoreAccounts {
+        fn to_account_metas(&self, _is_signer: Option<bool>) -> Vec<AccountMeta> {
+            vec![
+                AccountMeta::new(self.storage, false),
+                AccountMeta::new_readonly(self.owner, true),
+            ]
+        }
+    }
+
+    fn process_instruction(_: &Pubkey, accounts: &[AccountInfo], data: &[u8]) -> ProgramResult {
+        if !accounts[1].is_signer {
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+        match data[8] {
+            0 => Err(ProgramError::Custom(0)),
+            value => {
+                accounts[0].try_borrow_mut_data()?[0] = value;
+                Ok(())
+            }
+        }
+    }
+
+    fn program_test() -> ProgramTest {
+        let mut program_test =
+            ProgramTest::new("storage", PROGRAM_ID, processor!(process_instruction));
+        program_test.add_account(
+            STORAGE,
+            Account {
+                lamports: 1_000_000_000,
+                data: vec![0],
+                owner: PROGRAM_ID,
+                ..Account::default()
+            },
+        );
+        program_test
+    }
+
+    async fn store(client: &mut ProgramTestClient, value: u8) -> Result<(), Error> {
+        let owner = Keypair::new();
+        let accounts = StoreAccounts {
+            storage: STORAGE,
+            owner: owner.pubkey(),
+        };
+        client
+            .send_instruction(PROGRAM_ID, Store { value }, accounts, [owner])
+            .await
+    }
+
+    async fn stored_value(client: &mut ProgramTestClient) -> u8 {
+        client.get_account(STORAGE).await.unwrap().unwrap().data[0]
+    }
+
+    #[tokio::test]
+    async fn test_send_instruction() {
+        let mut client = ProgramTestClient::new(program_test()).await;
+
+        store(&mut client, 42).await.unwrap();
+        assert_eq!(stored_value(&mut client).await, 42);
+
+        let error = store(&mut client, 0).await.unwrap_err();
+        assert!(matches!(error, Error::BanksClient(_)), "{error:?}");
+        assert_eq!(stored_value(&mut client).await, 42);
+    }
+
+    #[test]
+    fn test_run_fuzz_iteration() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let iteration = |data: &[u8]| {
+            run_fuzz_iteration(&runtime, data, async {
+                let mut client = ProgramTestClient::new(program_test()).await;
+                let value = random_value::<u8>();
+                let result = store(&mut client, value).await;
+                (value, result.is_ok(), stored_value(&mut client).await)
+            })
+        };
+
+        // The values are read from the input, so the same input executes the same instructions
+        let data = [5, 6, 7, 8, 9];
+        let (value, success, stored) = iteration(&data);
+        assert_eq!(iteration(&data), (value, success, stored));
+        assert_eq!(success, value != 0);
+        assert_eq!(stored, value);
+
+        // An exhausted input generates zeros, which are rejected by the program
+        assert_eq!(iteration(&[]), (0, false, 0));
+    }
+}
@@ -1,39 +1,189 @@
+use std::cell::RefCell;
+
 use rand::{
     distributions::{Alphanumeric, DistString},
-    Rng,
+    rngs::StdRng,
+    Rng, RngCore, SeedableRng,
 };
-use trdelnik_client::{Keypair, Pubkey};
+use trdelnik_client::{solana_sdk::signer::keypair::keypair_from_seed, Keypair, Pubkey};
+
+use crate::arbitrary::Arbitrary;
+
+/// Environment variable used to pass the master seed to the fuzz test binary,
+/// it is set by `trdelnik fuzz run --seed <SEED>`.
+pub const SEED_ENV_VAR: &str = "TRDELNIK_FUZZ_SEED";
+
+tokio::task_local! {
+    static SEQUENCE_RNG: RefCell<FuzzRng>;
+}
+
+/// The RNG all the fuzz inputs are generated from.
+pub enum FuzzRng {
+    /// RNG seeded by the seed of the sequence or flow.
+    Seeded(Box<StdRng>),
+    /// Reads the random values from the input of a coverage-guided fuzzing engine,
+    /// so the engine is able to mutate them. Zeros are returned once the input is exhausted.
+    Input { data: Vec<u8>, position: usize },
+}
+
+impl RngCore for FuzzRng {
+    fn next_u32(&mut self) -> u32 {
+        let mut bytes = [0u8; 4];
+        self.fill_bytes(&mut bytes);
+        u32::from_le_bytes(bytes)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let mut bytes = [0u8; 8];
+        self.fill_bytes(&mut bytes);
+        u64::from_le_bytes(bytes)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        match self {
+            FuzzRng::Seeded(rng) => rng.fill_bytes(dest),
+            FuzzRng::Input { data, position } => {
+                let available = data.len().saturating_sub(*position).min(dest.len());
+                dest[..available].copy_from_slice(&data[*position..*position + available]);
+                dest[available..].fill(0);
+                *position += available;
+            }
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// Derives the seed of the sequence with the index `curr_seq_n` from the master seed.
+///
+/// The first sequence uses the master seed itself, so a failing sequence can be replayed
+/// alone by passing its seed as the master seed of a run with a single sequence.
+/// The other seeds are mixed by splitmix64, so the runs with close master seeds do not share sequences.
+pub fn sequence_seed(master_seed: u64, curr_seq_n: usize) -> u64 {
+    if curr_seq_n == 0 {
+        return master_seed;
+    }
+    splitmix64(master_seed.wrapping_add((curr_seq_n as u64).wrapping_mul(SPLITMIX64_GAMMA)))
+}
+
+const SPLITMIX64_GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+
+fn splitmix64(state: u64) -> u64 {
+    let mut z = state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Runs the future with the sequence RNG seeded by `seed`.
+///
+/// All the `random_*` helpers called inside of the future draw from this RNG.
+pub(crate) async fn with_seeded_rng<F: std::future::Future>(seed: u64, f: F) -> F::Output {
+    SEQUENCE_RNG
+        .scope(
+            RefCell::new(FuzzRng::Seeded(Box::new(StdRng::seed_from_u64(seed)))),
+            f,
+        )
+        .await
+}
+
+/// Runs the future with the RNG reading the input of a coverage-guided fuzzing engine.
+///
+/// All the `random_*` helpers and [Input](crate::Input)s called inside of the future draw
+/// from the input, so the engine mutates them with the coverage feedback.
+pub async fn with_input_rng<F: std::future::Future>(data: &[u8], f: F) -> F::Output {
+    SEQUENCE_RNG
+        .scope(
+            RefCell::new(FuzzRng::Input {
+                data: data.to_vec(),
+                position: 0,
+            }),
+            f,
+        )
+        .await
+}
+
+/// Calls `f` with the RNG of the current sequence.
+///
+/// Outside of a fuzz sequence (e.g. when called from the `main` function) a fresh RNG
+/// seeded from the thread RNG is used.
+pub fn with_rng<T>(f: impl FnOnce(&mut FuzzRng) -> T) -> T {
+    let mut f = Some(f);
+    SEQUENCE_RNG
+        .try_with(|rng| (f.take().unwrap())(&mut rng.borrow_mut()))
+        .unwrap_or_else(|_| {
+            let mut rng = FuzzRng::Seeded(Box::new(
+                StdRng::from_rng(rand::thread_rng()).expect("RNG initialization failed"),
+            ));
+            (f.take().unwrap())(&mut rng)
+        })
+}
+
+/// Generates a random value of any type implementing [Arbitrary].
+pub fn random_value<T: Arbitrary>() -> T {
+    with_rng(T::arbitrary)
+}
 
 pub fn random_pubkey() -> Pubkey {
-    Pubkey::new_unique()
+    Pubkey::new_from_array(with_rng(|rng| rng.gen()))
 }
 
 pub fn random_keypair() -> Keypair {
-    Keypair::new()
+    let seed: [u8; 32] = with_rng(|rng| rng.gen());
+    keypair_from_seed(&seed).expect("Keypair generation from seed failed")
 }
 
 pub fn random_i64(lower: i64, upper: i64) -> i64 {
-    rand::thread_rng().gen_range(lower..=upper)
+    with_rng(|rng| rng.gen_range(lower..=upper))
 }
 
 pub fn random_u64(lower: u64, upper: u64) -> u64 {
-    rand::thread_rng().gen_range(lower..=upper)
+    with_rng(|rng| rng.gen_range(lower..=upper))
 }
 
 pub fn random_bool() -> bool {
-    rand::thread_rng().gen()
+    with_rng(|rng| rng.gen())
 }
 
 pub fn random_string(length_min: u64, length_max: u64) -> String {
-    Alphanumeric.sample_string(
-        &mut rand::thread_rng(),
-        random_u64(length_min, length_max) as usize,
-    )
+    let length = random_u64(length_min, length_max) as usize;
+    with_rng(|rng| Alphanumeric.sample_string(rng, length))
 }
 
 pub fn random_bytes(length_min: u64, length_max: u64) -> Vec<u8> {
     let length = random_u64(length_min, length_max);
     let mut bytes = vec![0u8; length as usize];
-    rand::thread_rng().fill(&mut bytes[..]);
+    with_rng(|rng| rng.fill(&mut bytes[..]));
     bytes
 }
+
+/// Picks a random element of the slice, e.g. one of the prepared accounts.
+pub fn random_element<T: Clone>(items: &[T]) -> T {
+    if items.is_empty() {
+        panic!("Unable to pick a random element from an empty slice");
+    }
+    let index = with_rng(|rng| rng.gen_range(0..items.len()));
+    items[index].clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_sequence_seed() {
+        assert_eq!(sequence_seed(42, 0), 42);
+        // Runs with close master seeds do not share sequences
+        let seeds = |master_seed| {
+            (1..1000)
+                .map(|curr_seq_n| sequence_seed(master_seed, curr_seq_n))
+                .collect::<HashSet<_>>()
+        };
+        assert!(seeds(42).is_disjoint(&seeds(43)));
+        assert_eq!(seeds(42).len(), 999);
+    }
+}
@@ -0,0 +1,98 @@
+use std::future::Future;
+
+/// Minimizes the failing `sequence` using the delta debugging (ddmin) algorithm.
+///
+/// `fails` re-executes the given subsequence and returns `true` when it still fails.
+/// The returned sequence still fails and removing any of its items makes it pass,
+/// provided the failure is deterministic.
+pub(crate) async fn minimize<T, F, Fut>(sequence: Vec<T>, mut fails: F) -> Vec<T>
+where
+    T: Clone,
+    F: FnMut(Vec<T>) -> Fut,
+    Fut: Future<Output = bool>,
+{
+    let mut failing = sequence;
+    let mut n_chunks = 2;
+
+    while failing.len() >= 2 {
+        let chunks = split(&failing, n_chunks);
+        let mut reduced = false;
+
+        // Try to find a single chunk which still fails
+        for chunk in chunks.iter() {
+            if fails(chunk.clone()).await {
+                failing = chunk.clone();
+                n_chunks = 2;
+                reduced = true;
+                break;
+            }
+        }
+
+        // Try to remove a single chunk
+        if !reduced && n_chunks > 2 {
+            for i in 0..chunks.len() {
+                let complement = chunks
+                    .iter()
+                    .enumerate()
+                    .filter(|(j, _)| *j != i)
+                    .flat_map(|(_, chunk)| chunk.iter().cloned())
+                    .collect::<Vec<_>>();
+                if fails(complement.clone()).await {
+                    failing = complement;
+                    n_chunks = (n_chunks - 1).max(2);
+                    reduced = true;
+                    break;
+                }
+            }
+        }
+
+        if !reduced {
+            if n_chunks >= failing.len() {
+                break;
+            }
+            n_chunks = (n_chunks * 2).min(failing.len());
+        }
+    }
+    failing
+}
+
+fn split<T: Clone>(sequence: &[T], n_chunks: usize) -> Vec<Vec<T>> {
+    let mut chunks = Vec::with_capacity(n_chunks);
+    let mut start = 0;
+    for i in 0..n_chunks {
+        let end = start + (sequence.len() - start) / (n_chunks - i);
+        chunks.push(sequence[start..end].to_vec());
+        start = end;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_minimize_to_single_item() {
+        let sequence = (0..100).collect::<Vec<_>>();
+        let minimized = minimize(sequence, |seq| async move { seq.contains(&42) }).await;
+        assert_eq!(minimized, vec![42]);
+    }
+
+    #[tokio::test]
+    async fn test_minimize_keeps_order() {
+        let sequence = (0..64).collect::<Vec<_>>();
+        // Fails only when 3 is executed before 50 and 60
+        let minimized = minimize(sequence, |seq| async move {
+            let pos = |x| seq.iter().position(|&i| i == x);
+            matches!((pos(3), pos(50), pos(60)), (Some(a), Some(b), Some(c)) if a < b && b < c)
+        })
+        .await;
+        assert_eq!(minimized, vec![3, 50, 60]);
+    }
+
+    #[test]
+    fn test_split() {
+        let chunks = split(&[1, 2, 3, 4, 5], 2);
+        assert_eq!(chunks, vec![vec![1, 2], vec![3, 4, 5]]);
+    }
+}
@@ -0,0 +1,157 @@
+use std::{collections::BTreeMap, io, path::Path, time::Duration};
+
+use serde::Serialize;
+
+/// Environment variable with the path of the JSON report of the run,
+/// it is set by `trdelnik fuzz run --report <PATH>`.
+pub const REPORT_ENV_VAR: &str = "TRDELNIK_FUZZ_REPORT";
+
+/// Statistics of a single flow collected over all sequences of the run.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct FlowStats {
+    pub name: &'static str,
+    /// How many times the flow was selected to run.
+    pub executions: u64,
+    /// How many times the flow could not be selected because of its guard.
+    pub disabled: u64,
+    /// Number of the transactions of the flow rejected by the program, by the error code.
+    pub failed_transactions: BTreeMap<String, u64>,
+}
+
+impl FlowStats {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            executions: 0,
+            disabled: 0,
+            failed_transactions: BTreeMap::new(),
+        }
+    }
+
+    pub fn record_failure(&mut self, code: String) {
+        *self.failed_transactions.entry(code).or_default() += 1;
+    }
+}
+
+/// Statistics collected over all sequences of the run.
+#[derive(Debug, Default)]
+pub(crate) struct RunStats {
+    pub flows: Vec<FlowStats>,
+    pub sequences: u64,
+    pub invariant_checks: u64,
+}
+
+impl RunStats {
+    pub fn report(&self, elapsed: Duration) -> RunReport {
+        let executions: u64 = self.flows.iter().map(|flow| flow.executions).sum();
+        let duration_secs = elapsed.as_secs_f64();
+        RunReport {
+            duration_secs,
+            sequences: self.sequences,
+            executions,
+            executions_per_second: if duration_secs > 0. {
+                executions as f64 / duration_secs
+            } else {
+                0.
+            },
+            invariant_checks: self.invariant_checks,
+            flows: self.flows.clone(),
+        }
+    }
+}
+
+/// The end-of-run report printed to stdout and optionally written as JSON.
+#[derive(Debug, Serialize)]
+pub(crate) struct RunReport {
+    pub duration_secs: f64,
+    pub sequences: u64,
+    pub executions: u64,
+    pub executions_per_second: f64,
+    pub invariant_checks: u64,
+    pub flows: Vec<FlowStats>,
+}
+
+impl RunReport {
+    pub fn print(&self) {
+        println!("Fuzzing finished in {:.1}s", self.duration_secs);
+        println!(
+            "  {} sequences, {} flow executions ({:.1}/s), {} invariant checks",
+            self.sequences, self.executions, self.executions_per_second, self.invariant_checks
+        );
+        println!("Flow statistics:");
+        for flow in &self.flows {
+            let share = if self.executions > 0 {
+                flow.executions as f64 * 100. / self.executions as f64
+            } else {
+                0.
+            };
+            let failed: u64 = flow.failed_transactions.values().sum();
+            println!(
+                "  {}: executed {} times ({share:.1}%), disabled {} times, {failed} rejected transactions",
+                flow.name, flow.executions, flow.disabled
+            );
+            for (code, count) in &flow.failed_transactions {
+                println!("    {code}: {count}");
+            }
+        }
+    }
+
+    pub fn write_json(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut stats = RunStats::default();
+        stats.flows.push(FlowStats::new("flow_coin"));
+        stats.flows.push(FlowStats::new("flow_push"));
+        stats.sequences = 2;
+        stats.invariant_checks = 10;
+        stats.flows[0].executions = 6;
+        stats.flows[1].executions = 4;
+        stats.flows[1].disabled = 3;
+        stats.flows[1].record_failure("ConstraintMut (2000)".to_owned());
+        stats.flows[1].record_failure("ConstraintMut (2000)".to_owned());
+        stats.flows[1].record_failure("Custom(1)".to_owned());
+
+        let report = stats.report(Duration::from_secs(2));
+        assert_eq!(report.sequences, 2);
+        assert_eq!(report.executions, 10);
+        assert_eq!(report.executions_per_second, 5.);
+        assert_eq!(report.invariant_checks, 10);
+        assert_eq!(
+            report.flows[1].failed_transactions,
+            BTreeMap::from([
+                ("ConstraintMut (2000)".to_owned(), 2),
+                ("Custom(1)".to_owned(), 1)
+            ])
+        );
+        assert_eq!(
+            stats.report(Duration::ZERO).executions_per_second,
+            0.,
+            "an instant run has no throughput"
+        );
+
+        let path = std::env::temp_dir()
+            .join(format!("trdelnik-report-{}", std::process::id()))
+            .join("report.json");
+        report.write_json(&path).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(json["executions"], 10);
+        assert_eq!(json["flows"][1]["disabled"], 3);
+        assert_eq!(
+            json["flows"][1]["failed_transactions"]["ConstraintMut (2000)"],
+            2
+        );
+    }
+}
@@ -0,0 +1,442 @@
+use std::fmt;
+
+use tracing::debug;
+use trdelnik_client::{
+    anyhow,
+    solana_sdk::{account::AccountSharedData, instruction::AccountMeta},
+    Client, Instruction, InstructionAccount, Keypair, Pubkey, Signer,
+};
+
+/// How an account of the instruction was replaced by an attacker-controlled lookalike.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Substitution {
+    /// A new account with the same data, but owned by another program (missing owner check).
+    DifferentOwner,
+    /// The original account passed without its signature (missing signer check).
+    MissingSigner,
+    /// A new empty account, e.g. in place of a PDA (missing address or seeds check).
+    Replaced,
+    /// Another account of the instruction passed once more in place of the account
+    /// (missing check of distinct accounts).
+    Duplicated(String),
+}
+
+impl fmt::Display for Substitution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Substitution::DifferentOwner => write!(f, "a lookalike owned by another program"),
+            Substitution::MissingSigner => write!(f, "the same account without its signature"),
+            Substitution::Replaced => write!(f, "a new account with a different address"),
+            Substitution::Duplicated(other) => write!(f, "the account `{other}`"),
+        }
+    }
+}
+
+/// Account substitution attack accepted by the program.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubstitutionFinding {
+    /// Name of the substituted account.
+    pub account: String,
+    pub substitution: Substitution,
+}
+
+impl fmt::Display for SubstitutionFinding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "account `{}` replaced by {}",
+            self.account, self.substitution
+        )
+    }
+}
+
+/// Sends copies of the instruction with one account substituted by an attacker-controlled
+/// lookalike and fails when any of them is not rejected by the program.
+///
+/// The accounts are generated in the `program_client` crate for every instruction,
+/// e.g. `turnstile_instruction::PUSH_ACCOUNTS`. The accounts without any owner, type
+/// or address check (e.g. a plain `Signer`) are only checked for the missing signature unless
+/// [with_unchecked](AccountSubstitution::with_unchecked) is called, as the program
+/// is expected to accept any account in their place. The substituted instructions are sent
+/// before the original one is, so the flow can continue normally when all of them were rejected.
+///
+/// The lookalikes are new keypairs, they do not draw from the RNG of the sequence, so the inputs
+/// of the following flows are the same as without the substitutions.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// async fn flow_push(client: Client, State(state): State<Keypair>) -> anyhow::Result<()> {
+///     let ix = turnstile_instruction::push_ix(
+///         turnstile::instruction::Push {},
+///         turnstile::accounts::UpdateState { state: state.pubkey() },
+///     );
+///     AccountSubstitution::new(ix.clone(), turnstile_instruction::PUSH_ACCOUNTS)
+///         .run(&client)
+///         .await?;
+///     client.send_transaction(&[ix], []).await?;
+///     Ok(())
+/// }
+/// ```
+pub struct AccountSubstitution {
+    instruction: Instruction,
+    accounts: Vec<InstructionAccount>,
+    signers: Vec<Keypair>,
+    skipped: Vec<String>,
+    substitute_unchecked: bool,
+}
+
+impl AccountSubstitution {
+    pub fn new(instruction: Instruction, accounts: &[InstructionAccount]) -> Self {
+        Self {
+            instruction,
+            accounts: accounts.to_vec(),
+            signers: vec![],
+            skipped: vec![],
+            substitute_unchecked: false,
+        }
+    }
+
+    /// Sets the signers of the original instruction, the payer of the client signs automatically.
+    pub fn with_signers(mut self, signers: impl IntoIterator<Item = Keypair>) -> Self {
+        self.signers.extend(signers);
+        self
+    }
+
+    /// Does not substitute the account, e.g. an account created by the instruction
+    /// or an account which is intentionally unchecked.
+    pub fn skip(mut self, account_name: &str) -> Self {
+        self.skipped.push(account_name.to_owned());
+        self
+    }
+
+    /// Substitutes also the accounts without any owner, type or address check by other accounts,
+    /// e.g. to find the accounts which should have been checked. Their missing signatures
+    /// are always tried.
+    pub fn with_unchecked(mut self) -> Self {
+        self.substitute_unchecked = true;
+        self
+    }
+
+    /// Sends all the substituted instructions and fails with the list of the accepted ones.
+    pub async fn run(self, client: &Client) -> anyhow::Result<()> {
+        let findings = self.findings(client).await?;
+        if !findings.is_empty() {
+            anyhow::bail!(
+                "The program accepted substituted accounts:\n{}",
+                findings
+                    .iter()
+                    .map(|finding| format!("  {finding}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+        Ok(())
+    }
+
+    /// Sends all the substituted instructions and returns the accepted ones.
+    pub async fn findings(&self, client: &Client) -> anyhow::Result<Vec<SubstitutionFinding>> {
+        let mut findings = vec![];
+        for (index, meta) in self.instruction.accounts.iter().enumerate() {
+            let account = self.account_name(index);
+            for (substitution, instruction, extra_signer) in
+                self.substitutions(client, index, meta).await?
+            {
+                debug!("substituting account {account} by {substitution}");
+                if self
+                    .send_substituted(client, instruction, extra_signer)
+                    .await
+                {
+                    findings.push(SubstitutionFinding {
+                        account: account.clone(),
+                        substitution,
+                    });
+                }
+            }
+        }
+        Ok(findings)
+    }
+
+    /// Name of the account at the index, the remaining accounts are not generated.
+    fn account_name(&self, index: usize) -> String {
+        self.accounts
+            .get(index)
+            .map(|account| account.name.to_owned())
+            .unwrap_or_else(|| format!("#{index}"))
+    }
+
+    /// Returns `true` when the substitution of the account at the index is tried, an unchecked
+    /// account is substituted only by the same account without its signature.
+    fn substitutes(&self, index: usize, substitution: &Substitution) -> bool {
+        !self.skipped.contains(&self.account_name(index))
+            && (*substitution == Substitution::MissingSigner
+                || self.substitute_unchecked
+                || self
+                    .accounts
+                    .get(index)
+                    .map_or(true, |account| account.checked))
+    }
+
+    async fn substitutions(
+        &self,
+        client: &Client,
+        index: usize,
+        meta: &AccountMeta,
+    ) -> anyhow::Result<Vec<(Substitution, Instruction, Option<Keypair>)>> {
+        let mut substitutions = vec![];
+
+        if self.substitutes(index, &Substitution::DifferentOwner) {
+            if let Some(mut account) = client.get_account(meta.pubkey).await? {
+                if !account.executable {
+                    // The copy is stored directly, it is owned by a program of the attacker
+                    let lookalike = Keypair::new();
+                    account.owner = Keypair::new().pubkey();
+                    client
+                        .set_account(lookalike.pubkey(), AccountSharedData::from(account))
+                        .await;
+                    substitutions.push((
+                        Substitution::DifferentOwner,
+                        self.replace(index, lookalike.pubkey(), meta.is_signer),
+                        meta.is_signer.then_some(lookalike),
+                    ));
+                }
+            }
+        }
+
+        // The payer signs every transaction, so its signature cannot be removed
+        if self.substitutes(index, &Substitution::MissingSigner)
+            && meta.is_signer
+            && meta.pubkey != client.payer().pubkey()
+        {
+            substitutions.push((
+                Substitution::MissingSigner,
+                self.replace(index, meta.pubkey, false),
+                None,
+            ));
+        }
+
+        if self.substitutes(index, &Substitution::Replaced) {
+            let replacement = Keypair::new();
+            substitutions.push((
+                Substitution::Replaced,
+                self.replace(index, replacement.pubkey(), meta.is_signer),
+                meta.is_signer.then_some(replacement),
+            ));
+        }
+
+        if let Some(other_index) = self.duplicate(index, &client.payer().pubkey()) {
+            let substitution = Substitution::Duplicated(self.account_name(other_index));
+            if self.substitutes(index, &substitution) {
+                let other = self.instruction.accounts[other_index].pubkey;
+                substitutions.push((
+                    substitution,
+                    self.replace(index, other, meta.is_signer),
+                    None,
+                ));
+            }
+        }
+
+        Ok(substitutions)
+    }
+
+    /// Index of the first other account of the instruction which can be passed in place
+    /// of the account at the index.
+    fn duplicate(&self, index: usize, payer: &Pubkey) -> Option<usize> {
+        let meta = &self.instruction.accounts[index];
+        self.instruction.accounts.iter().position(|other| {
+            other.pubkey != meta.pubkey
+                    // A signer can be duplicated only by an account whose keypair is available
+                    && (!meta.is_signer || self.can_sign(payer, &other.pubkey))
+        })
+    }
+
+    fn can_sign(&self, payer: &Pubkey, pubkey: &Pubkey) -> bool {
+        *pubkey == *payer || self.signers.iter().any(|signer| signer.pubkey() == *pubkey)
+    }
+
+    /// Copy of the instruction with the account at the index replaced.
+    fn replace(&self, index: usize, pubkey: Pubkey, is_signer: bool) -> Instruction {
+        let mut instruction = self.instruction.clone();
+        let meta = &mut instruction.accounts[index];
+        meta.pubkey = pubkey;
+        meta.is_signer = is_signer;
+        instruction
+    }
+
+    /// Returns `true` when the substituted instruction was executed successfully.
+    async fn send_substituted(
+        &self,
+        client: &Client,
+        instruction: Instruction,
+        extra_signer: Option<Keypair>,
+    ) -> bool {
+        let payer = client.payer().pubkey();
+        let required_signers = instruction
+            .accounts
+            .iter()
+            .filter(|meta| meta.is_signer && meta.pubkey != payer)
+            .map(|meta| meta.pubkey)
+            .collect::<Vec<_>>();
+        // Signers of the removed signatures would make the signing of the transaction fail
+        let signers = self
+            .signers
+            .iter()
+            .chain(extra_signer.as_ref())
+            .filter(|signer| required_signers.contains(&signer.pubkey()))
+            .collect::<Vec<_>>();
+        match client.send_transaction(&[instruction], signers).await {
+            Ok(_) => true,
+            Err(e) => {
+                debug!("substituted instruction rejected: {e}");
+                false
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SUBSTITUTIONS: [Substitution; 3] = [
+        Substitution::DifferentOwner,
+        Substitution::MissingSigner,
+        Substitution::Replaced,
+    ];
+
+    const ACCOUNTS: &[InstructionAccount] = &[
+        InstructionAccount {
+            name: "state",
+            checked: true,
+        },
+        InstructionAccount {
+            name: "user",
+            checked: false,
+        },
+        InstructionAccount {
+            name: "authority",
+            checked: true,
+        },
+        InstructionAccount {
+            name: "payer",
+            checked: true,
+        },
+    ];
+
+    struct Fixture {
+        user: Keypair,
+        payer: Pubkey,
+        instruction: Instruction,
+    }
+
+    fn fixture() -> Fixture {
+        let user = Keypair::new();
+        let payer = Pubkey::new_unique();
+        let instruction = Instruction {
+            program_id: Pubkey::new_unique(),
+            accounts: vec![
+                AccountMeta::new(Pubkey::new_unique(), false),
+                AccountMeta::new_readonly(user.pubkey(), true),
+                AccountMeta::new_readonly(Pubkey::new_unique(), true),
+                AccountMeta::new(payer, true),
+                // A remaining account, which is not generated in `ACCOUNTS`
+                AccountMeta::new(Pubkey::new_unique(), false),
+            ],
+            data: vec![],
+        };
+        Fixture {
+            user,
+            payer,
+            instruction,
+        }
+    }
+
+    fn substituted(substitution: &AccountSubstitution, index: usize) -> Vec<Substitution> {
+        SUBSTITUTIONS
+            .into_iter()
+            .chain([Substitution::Duplicated("state".to_owned())])
+            .filter(|kind| substitution.substitutes(index, kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_substitutes() {
+        let Fixture { instruction, .. } = fixture();
+        let all = substituted(&AccountSubstitution::new(instruction.clone(), &[]), 0);
+
+        let substitution = AccountSubstitution::new(instruction.clone(), ACCOUNTS);
+        assert_eq!(substituted(&substitution, 0), all);
+        assert_eq!(substitution.account_name(4), "#4");
+        assert_eq!(substituted(&substitution, 4), all);
+        // Only the signature of an unchecked account is expected to be checked
+        assert_eq!(
+            substituted(&substitution, 1),
+            vec![Substitution::MissingSigner]
+        );
+
+        let substitution = AccountSubstitution::new(instruction.clone(), ACCOUNTS).with_unchecked();
+        assert_eq!(substituted(&substitution, 1), all);
+
+        let substitution = AccountSubstitution::new(instruction, ACCOUNTS)
+            .skip("user")
+            .skip("state");
+        assert!(substituted(&substitution, 0).is_empty());
+        assert!(substituted(&substitution, 1).is_empty());
+        assert_eq!(substituted(&substitution, 2), all);
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let Fixture {
+            user,
+            payer,
+            instruction,
+        } = fixture();
+        let substitution = AccountSubstitution::new(instruction, ACCOUNTS);
+        assert_eq!(substitution.duplicate(0, &payer), Some(1));
+        assert_eq!(substitution.duplicate(1, &payer), Some(3));
+        // Only the payer can sign in place of the authority
+        assert_eq!(substitution.duplicate(2, &payer), Some(3));
+        assert_eq!(substitution.duplicate(3, &payer), None);
+
+        let substitution = substitution.with_signers([user]);
+        assert_eq!(substitution.duplicate(2, &payer), Some(1));
+    }
+
+    #[test]
+    fn test_replace() {
+        let Fixture { instruction, .. } = fixture();
+        let substitution = AccountSubstitution::new(instruction.clone(), ACCOUNTS);
+        let authority = &instruction.accounts[2];
+
+        // DifferentOwner and Replaced
+        let lookalike = Pubkey::new_unique();
+        let replaced = substitution.replace(2, lookalike, true);
+        assert_eq!(
+            replaced.accounts[2],
+            AccountMeta::new_readonly(lookalike, true)
+        );
+
+        // MissingSigner
+        let replaced = substitution.replace(2, authority.pubkey, false);
+        assert_eq!(
+            replaced.accounts[2],
+            AccountMeta::new_readonly(authority.pubkey, false)
+        );
+
+        // Duplicated
+        let other = instruction.accounts[0].pubkey;
+        let replaced = substitution.replace(2, other, true);
+        assert_eq!(replaced.accounts[2], AccountMeta::new_readonly(other, true));
+
+        for replaced in [
+            substitution.replace(2, lookalike, true),
+            substitution.replace(2, other, false),
+        ] {
+            assert_eq!(replaced.program_id, instruction.program_id);
+            for index in [0, 1, 3, 4] {
+                assert_eq!(replaced.accounts[index], instruction.accounts[index]);
+            }
+        }
+    }
+}
@@ -0,0 +1,22 @@
+use honggfuzz::fuzz;
+use trdelnik_client::tokio::runtime::Runtime;
+use trdelnik_fuzz::program_test::{run_fuzz_iteration, ProgramTest, ProgramTestClient};
+
+fn program_test() -> ProgramTest {
+    let program_test = ProgramTest::default();
+    // program_test.add_program("d21", d21::id(), trdelnik_fuzz::program_test::processor!(d21::entry));
+    program_test
+}
+
+fn main() {
+    let runtime = Runtime::new().expect("Unable to create the runtime");
+    loop {
+        fuzz!(|data: &[u8]| {
+            run_fuzz_iteration(&runtime, data, async {
+                let _client = ProgramTestClient::new(program_test()).await;
+                // Send the instructions with the inputs generated by `trdelnik_fuzz::random_value`
+                // and the `random_*` helpers, they are read from the honggfuzz input
+            });
+        });
+    }
+}
@@ -1,9 +1,20 @@
 use std::{
-    io,
+    cell::RefCell,
+    collections::HashMap,
+    fmt, io,
+    path::Path,
     sync::{Arc, Mutex, MutexGuard},
 };
 
-use tracing_subscriber::fmt::MakeWriter;
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id},
+    Subscriber,
+};
+use tracing_subscriber::{fmt::MakeWriter, layer::Context, registry::LookupSpan, Layer};
+
+/// Name of the span field identifying the sequence the logs belong to.
+const SEQUENCE_FIELD: &str = "curr_sequence_number";
 
 #[derive(Debug, Clone)]
 pub struct MemoryWriter {
@@ -27,18 +38,16 @@ impl MemoryWriter {
             .map_err(|_| io::Error::from(io::ErrorKind::Other))
     }
 
-    pub fn print(&self, curr_seq_n: usize) {
+    pub fn print(&self) {
         let target = self.buf().unwrap();
         println!("Printing {} bytes:", target.len());
-        println!("Checking for curr_sequence_number={curr_seq_n}");
-        let resulting_log = String::from_utf8(target.to_vec()).unwrap();
+        print!("{}", String::from_utf8_lossy(&target));
+    }
 
-        for log in resulting_log.split("\n\n") {
-            let pure_string = String::from_utf8(strip_ansi_escapes::strip(log).unwrap()).unwrap();
-            if pure_string.contains(format!("curr_sequence_number={curr_seq_n}").as_str()) {
-                print!("{log}\n\n");
-            }
-        }
+    /// Writes the log without the ANSI escape codes to the file.
+    pub fn write_to_file(&self, path: &Path) -> io::Result<()> {
+        let target = self.buf()?;
+        std::fs::write(path, strip_ansi_escapes::strip(&*target)?)
     }
 }
 
@@ -64,45 +73,161 @@ impl MakeWriter<'_> for MemoryWriter {
     }
 }
 
-// pub struct MakeBySequenceWriter {
-//     writers: HashMap<usize, MemoryWriter>,
-//     default_writer: MemoryWriter,
-// }
-
-// impl MakeBySequenceWriter {
-//     pub fn new(n_seq: usize) -> Self {
-//         let writers = (0..n_seq)
-//             .map(|i| (i, MemoryWriter::new()))
-//             .collect::<HashMap<_, _>>();
-//         Self {
-//             writers,
-//             default_writer: MemoryWriter::new(),
-//         }
-//     }
-
-//     pub fn get_writers(&self) -> HashMap<usize, MemoryWriter> {
-//         self.writers.clone()
-//     }
-// }
-
-// impl MakeWriter<'_> for MakeBySequenceWriter {
-//     type Writer = MemoryWriter;
-
-//     fn make_writer(&self) -> Self::Writer {
-//         self.default_writer.make_writer()
-//     }
-
-//     fn make_writer_for(&self, meta: &Metadata<'_>) -> Self::Writer {
-//         match meta.fields().field("curr_sequence_number") {
-//             Some(i) => {
-//                 let i = i.as_ref().parse::<usize>().unwrap();
-//                 println!("curr_sequence_number: {}", &i);
-//                 self.writers
-//                     .get(&i)
-//                     .expect(&format!("Writer for {i} was not found"))
-//                     .clone()
-//             }
-//             None => self.default_writer.clone(),
-//         }
-//     }
-// }
+thread_local! {
+    /// Sequence numbers of the spans entered on the current thread.
+    static ENTERED_SEQUENCES: RefCell<Vec<usize>> = RefCell::new(vec![]);
+}
+
+/// Routes the logs to a separate [MemoryWriter] per sequence.
+///
+/// The sequence is identified by the `curr_sequence_number` field of the entered span,
+/// which is tracked by the [SequenceLayer]. Logs outside of any sequence go to the default writer.
+#[derive(Debug, Clone)]
+pub struct MakeBySequenceWriter {
+    writers: Arc<Mutex<HashMap<usize, MemoryWriter>>>,
+    default_writer: MemoryWriter,
+}
+
+impl MakeBySequenceWriter {
+    pub fn new() -> Self {
+        Self {
+            writers: Default::default(),
+            default_writer: MemoryWriter::new(),
+        }
+    }
+
+    /// Gets the writer of the sequence with the index `curr_seq_n`.
+    pub fn writer(&self, curr_seq_n: usize) -> MemoryWriter {
+        self.writers
+            .lock()
+            .unwrap()
+            .entry(curr_seq_n)
+            .or_insert_with(MemoryWriter::new)
+            .clone()
+    }
+
+    /// Writes the log of every sequence to `sequence-<index>.log` in the directory.
+    pub fn write_to_dir(&self, dir: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        for (curr_seq_n, writer) in self.writers.lock().unwrap().iter() {
+            writer.write_to_file(&dir.join(format!("sequence-{curr_seq_n}.log")))?;
+        }
+        Ok(())
+    }
+}
+
+impl MakeWriter<'_> for MakeBySequenceWriter {
+    type Writer = MemoryWriter;
+
+    fn make_writer(&self) -> Self::Writer {
+        match ENTERED_SEQUENCES.with(|sequences| sequences.borrow().last().copied()) {
+            Some(curr_seq_n) => self.writer(curr_seq_n),
+            None => self.default_writer.clone(),
+        }
+    }
+}
+
+struct SequenceNumber(usize);
+
+struct SequenceNumberVisitor(Option<usize>);
+
+impl Visit for SequenceNumberVisitor {
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        if field.name() == SEQUENCE_FIELD {
+            self.0 = Some(value as usize);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == SEQUENCE_FIELD {
+            self.0 = format!("{value:?}").parse().ok();
+        }
+    }
+}
+
+/// Tracks the `curr_sequence_number` field of the entered spans for the [MakeBySequenceWriter].
+pub struct SequenceLayer;
+
+impl<S> Layer<S> for SequenceLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = SequenceNumberVisitor(None);
+        attrs.record(&mut visitor);
+        if let (Some(curr_seq_n), Some(span)) = (visitor.0, ctx.span(id)) {
+            span.extensions_mut().insert(SequenceNumber(curr_seq_n));
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(SequenceNumber(curr_seq_n)) = span.extensions().get::<SequenceNumber>() {
+                ENTERED_SEQUENCES.with(|sequences| sequences.borrow_mut().push(*curr_seq_n));
+            }
+        }
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if span.extensions().get::<SequenceNumber>().is_some() {
+                ENTERED_SEQUENCES.with(|sequences| sequences.borrow_mut().pop());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing::{debug, debug_span};
+    use tracing_subscriber::prelude::*;
+
+    fn contents(writer: &MemoryWriter) -> String {
+        String::from_utf8(writer.buf().unwrap().clone()).unwrap()
+    }
+
+    #[test]
+    fn test_logs_by_sequence() {
+        let writer = MakeBySequenceWriter::new();
+        let subscriber = tracing_subscriber::registry().with(SequenceLayer).with(
+            tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(writer.clone()),
+        );
+
+        tracing::subscriber::with_default(subscriber, || {
+            debug!("outside of the sequences");
+            let first = debug_span!("Sequence::started", curr_sequence_number = %0);
+            let second = debug_span!("Sequence::started", curr_sequence_number = %1);
+            first.in_scope(|| debug!("flow of the first sequence"));
+            second.in_scope(|| {
+                debug!("flow of the second sequence");
+                debug_span!("Nested").in_scope(|| debug!("nested log of the second sequence"));
+            });
+            first.in_scope(|| debug!("invariant of the first sequence"));
+        });
+
+        let first = contents(&writer.writer(0));
+        assert!(first.contains("flow of the first sequence"));
+        assert!(first.contains("invariant of the first sequence"));
+        assert!(!first.contains("second sequence"));
+
+        let second = contents(&writer.writer(1));
+        assert!(second.contains("flow of the second sequence"));
+        assert!(second.contains("nested log of the second sequence"));
+        assert!(!second.contains("first sequence"));
+
+        let default = contents(&writer.default_writer);
+        assert!(default.contains("outside of the sequences"));
+        assert!(!default.contains("flow of"));
+
+        let dir = std::env::temp_dir().join(format!("trdelnik-logs-{}", std::process::id()));
+        writer.write_to_dir(&dir).unwrap();
+        let first_file = std::fs::read_to_string(dir.join("sequence-0.log")).unwrap();
+        let second_file = std::fs::read_to_string(dir.join("sequence-1.log")).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(first_file, first);
+        assert_eq!(second_file, second);
+    }
+}
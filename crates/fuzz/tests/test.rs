@@ -0,0 +1,63 @@
+use trdelnik_fuzz::{
+    rand::{rngs::StdRng, SeedableRng},
+    Arbitrary, FuzzRng,
+};
+
+#[derive(Debug, Clone, PartialEq, Arbitrary)]
+struct Pair<T> {
+    first: T,
+    second: Option<T>,
+}
+
+#[derive(Debug, Clone, PartialEq, Arbitrary)]
+enum Action<T: Clone, const N: usize> {
+    Noop,
+    Transfer(T, u8),
+    Set { value: T, bytes: [u8; N] },
+}
+
+fn seeded_rng(seed: u64) -> FuzzRng {
+    FuzzRng::Seeded(Box::new(StdRng::seed_from_u64(seed)))
+}
+
+#[test]
+fn derive_arbitrary_generic_struct() {
+    let mut rng = seeded_rng(42);
+    let pairs = (0..10)
+        .map(|_| Pair::<u64>::arbitrary(&mut rng))
+        .collect::<Vec<_>>();
+
+    let mut rng = seeded_rng(42);
+    let replayed = (0..10)
+        .map(|_| Pair::<u64>::arbitrary(&mut rng))
+        .collect::<Vec<_>>();
+    assert_eq!(pairs, replayed);
+
+    // An exhausted input generates zeros, i.e. the minimal values and `None`
+    let mut rng = FuzzRng::Input {
+        data: vec![],
+        position: 0,
+    };
+    assert_eq!(
+        Pair::<i8>::arbitrary(&mut rng),
+        Pair {
+            first: i8::MIN,
+            second: None
+        }
+    );
+}
+
+#[test]
+fn derive_arbitrary_generic_enum() {
+    let mut rng = seeded_rng(42);
+    let actions = (0..100)
+        .map(|_| Action::<String, 3>::arbitrary(&mut rng))
+        .collect::<Vec<_>>();
+    assert!(actions.contains(&Action::Noop));
+    assert!(actions
+        .iter()
+        .any(|action| matches!(action, Action::Transfer(..))));
+    assert!(actions
+        .iter()
+        .any(|action| matches!(action, Action::Set { .. })));
+}
@@ -5,7 +5,7 @@
 
 use darling::FromMeta;
 use proc_macro::TokenStream;
-use syn::{spanned::Spanned, ItemFn};
+use syn::{parse_macro_input, spanned::Spanned, AttributeArgs, ItemFn};
 
 #[derive(Debug, FromMeta)]
 struct MacroArgs {
@@ -13,6 +13,12 @@ struct MacroArgs {
     _root: Option<String>,
 }
 
+#[derive(Debug, FromMeta)]
+struct FuzzMacroArgs {
+    #[darling(default)]
+    current_thread: bool,
+}
+
 /// The macro starts the Solana validator (localnet), runs your program test and then shuts down the validator.
 /// - The test implicitly returns [anyhow::Result<()>](https://docs.rs/anyhow/latest/anyhow/type.Result.html).
 /// - All tests are run sequentially - each test uses a new/reset validator. (See [serial_test::serial](https://docs.rs/serial_test/latest/serial_test/attr.serial.html))
@@ -88,8 +94,25 @@ pub fn trdelnik_test(_args: TokenStream, input: TokenStream) -> TokenStream {
     .into()
 }
 
+/// The macro runs the fuzz test in the `main` function of the fuzz test binary.
+/// - Async support is provided by Tokio: [tokio::main(flavor = "multi_thread")](https://docs.rs/tokio/latest/tokio/attr.main.html).
+/// - The macro accepts one optional flag `current_thread` running the fuzz test on a single-threaded runtime.
+///      - Example: `#[trdelnik_fuzz(current_thread)]`
 #[proc_macro_attribute]
-pub fn trdelnik_fuzz(_args: TokenStream, input: TokenStream) -> TokenStream {
+pub fn trdelnik_fuzz(args: TokenStream, input: TokenStream) -> TokenStream {
+    let attr_args = parse_macro_input!(args as AttributeArgs);
+    let macro_args = match FuzzMacroArgs::from_list(&attr_args) {
+        Ok(macro_args) => macro_args,
+        Err(error) => {
+            return TokenStream::from(error.write_errors());
+        }
+    };
+    let flavor = if macro_args.current_thread {
+        "current_thread"
+    } else {
+        "multi_thread"
+    };
+
     let input_fn: ItemFn =
         syn::parse(input).expect("'trdelnik_fuzz' attribute is applicable only to async fn");
 
@@ -102,7 +125,7 @@ pub fn trdelnik_fuzz(_args: TokenStream, input: TokenStream) -> TokenStream {
     quote::quote_spanned!(input_fn_span=>
         #(#input_fn_attrs)*
 
-        #[tokio::main(flavor = "multi_thread")]
+        #[tokio::main(flavor = #flavor)]
         async fn #input_fn_name(#input_fn_inputs) -> trdelnik_client::anyhow::Result<()> {
             let test = async {
                 #input_fn_body
@@ -121,3 +144,103 @@ pub fn trdelnik_fuzz(_args: TokenStream, input: TokenStream) -> TokenStream {
     )
     .into()
 }
+
+/// Derives `trdelnik_fuzz::Arbitrary` for structs and enums whose fields implement it,
+/// so they can be generated by the fuzzer and received in flows through `trdelnik_fuzz::Input`.
+///
+/// The type parameters of generic structs and enums are required to implement `Arbitrary` too.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[derive(Debug, Clone, Arbitrary)]
+/// struct CoinInput {
+///     dummy_arg: String,
+/// }
+/// ```
+#[proc_macro_derive(Arbitrary)]
+pub fn derive_arbitrary(input: TokenStream) -> TokenStream {
+    let input: syn::DeriveInput =
+        syn::parse(input).expect("'Arbitrary' can be derived only for structs and enums");
+
+    let name = input.ident;
+    let mut generics = input.generics;
+    let type_params = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let where_clause = generics.make_where_clause();
+    for type_param in type_params {
+        where_clause
+            .predicates
+            .push(syn::parse_quote!(#type_param: trdelnik_fuzz::Arbitrary));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let body = match input.data {
+        syn::Data::Struct(data) => arbitrary_fields(quote::quote!(Self), &data.fields),
+        syn::Data::Enum(data) => {
+            let n_variants = data.variants.len();
+            if n_variants == 0 {
+                return syn::Error::new(
+                    name.span(),
+                    "'Arbitrary' cannot be derived for empty enums",
+                )
+                .to_compile_error()
+                .into();
+            }
+            let variants = data.variants.iter().enumerate().map(|(i, variant)| {
+                let variant_name = &variant.ident;
+                let constructor =
+                    arbitrary_fields(quote::quote!(Self::#variant_name), &variant.fields);
+                quote::quote!(#i => #constructor,)
+            });
+            quote::quote! {
+                match trdelnik_fuzz::rand::Rng::gen_range(rng, 0..#n_variants) {
+                    #(#variants)*
+                    _ => unreachable!(),
+                }
+            }
+        }
+        syn::Data::Union(data) => {
+            return syn::Error::new(
+                data.union_token.span(),
+                "'Arbitrary' cannot be derived for unions",
+            )
+            .to_compile_error()
+            .into()
+        }
+    };
+
+    quote::quote! {
+        impl #impl_generics trdelnik_fuzz::Arbitrary for #name #ty_generics #where_clause {
+            fn arbitrary(rng: &mut trdelnik_fuzz::FuzzRng) -> Self {
+                #body
+            }
+        }
+    }
+    .into()
+}
+
+fn arbitrary_fields(
+    constructor: proc_macro2::TokenStream,
+    fields: &syn::Fields,
+) -> proc_macro2::TokenStream {
+    match fields {
+        syn::Fields::Named(fields) => {
+            let fields = fields.named.iter().map(|field| {
+                let field_name = &field.ident;
+                quote::quote!(#field_name: trdelnik_fuzz::Arbitrary::arbitrary(rng))
+            });
+            quote::quote!(#constructor { #(#fields),* })
+        }
+        syn::Fields::Unnamed(fields) => {
+            let fields = fields
+                .unnamed
+                .iter()
+                .map(|_| quote::quote!(trdelnik_fuzz::Arbitrary::arbitrary(rng)));
+            quote::quote!(#constructor ( #(#fields),* ))
+        }
+        syn::Fields::Unit => constructor,
+    }
+}
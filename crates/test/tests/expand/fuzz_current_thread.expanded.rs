@@ -0,0 +1,28 @@
+#[tokio::main(flavor = "current_thread")]
+async fn main() -> trdelnik_client::anyhow::Result<()> {
+    let test = async {
+        {
+            FuzzTestBuilder::new()
+                .initialize_validator(initialize_validator)
+                .add_flow(flow_increment)
+                .start(2, 200)
+                .await;
+        }
+        Ok::<(), trdelnik_client::anyhow::Error>(())
+    };
+    let result = std::panic::AssertUnwindSafe(test).catch_unwind().await;
+    if !result.is_ok() {
+        ::core::panicking::panic("assertion failed: result.is_ok()")
+    }
+    let final_result = result.unwrap();
+    if let Err(error) = final_result {
+        trdelnik_client::error_reporter::report_error(&error);
+        return Err(error);
+    }
+    {
+        ::std::io::_print(
+            format_args!("Fuzzing finished, there were no invariant violations\n"),
+        );
+    };
+    Ok(())
+}
@@ -0,0 +1,8 @@
+#[trdelnik_test::trdelnik_fuzz(current_thread)]
+async fn main() {
+    FuzzTestBuilder::new()
+        .initialize_validator(initialize_validator)
+        .add_flow(flow_increment)
+        .start(2, 200)
+        .await;
+}
@@ -26,6 +26,20 @@ pub mod turnstile_instruction {
             accounts: accounts.to_account_metas(None),
         }
     }
+    pub const INITIALIZE_ACCOUNTS: &[InstructionAccount] = &[
+        InstructionAccount {
+            name: "state",
+            checked: true,
+        },
+        InstructionAccount {
+            name: "user",
+            checked: false,
+        },
+        InstructionAccount {
+            name: "system_program",
+            checked: true,
+        },
+    ];
     pub async fn coin(
         client: &Client,
         parameters: turnstile::instruction::Coin,
@@ -46,6 +60,10 @@ pub mod turnstile_instruction {
             accounts: accounts.to_account_metas(None),
         }
     }
+    pub const COIN_ACCOUNTS: &[InstructionAccount] = &[InstructionAccount {
+        name: "state",
+        checked: true,
+    }];
     pub async fn push(
         client: &Client,
         parameters: turnstile::instruction::Push,
@@ -66,4 +84,8 @@ pub mod turnstile_instruction {
             accounts: accounts.to_account_metas(None),
         }
     }
+    pub const PUSH_ACCOUNTS: &[InstructionAccount] = &[InstructionAccount {
+        name: "state",
+        checked: true,
+    }];
 }
@@ -2,9 +2,10 @@ use std::ops::Deref;
 
 use program_client::turnstile_instruction::{self, PROGRAM_ID};
 use trdelnik_client::{
-    tokio, trdelnik_fuzz, Client, FutureExt, Id, Keypair, Signer, System, Validator,
+    tokio, trdelnik_fuzz, Client, ClientError, FutureExt, Id, Keypair, Signer, System,
+    Validator,
 };
-use trdelnik_fuzz::{FuzzTestBuilder, State};
+use trdelnik_fuzz::{FuzzTestBuilder, State, StateRef};
 use turnstile::{accounts, instruction, State as AccountState};
 
 #[derive(Debug)]
@@ -36,7 +37,10 @@ fn initialize_validator() -> Validator {
     validator
 }
 
-async fn flow_push(client: Client, State(mut turnstile_exp_state): State<TurnstileExpectedState>) {
+async fn flow_push(
+    client: Client,
+    State(mut turnstile_exp_state): State<TurnstileExpectedState>,
+) -> Result<(), ClientError> {
     turnstile_instruction::push(
         &client,
         instruction::Push {},
@@ -45,17 +49,20 @@ async fn flow_push(client: Client, State(mut turnstile_exp_state): State<Turnsti
         },
         None,
     )
-    .await
-    .expect("push failed");
+    .await?;
     if turnstile_exp_state.locked {
         turnstile_exp_state.res = false;
     } else {
         turnstile_exp_state.locked = true;
         turnstile_exp_state.res = true;
     }
+    Ok(())
 }
 
-async fn flow_coin(client: Client, State(mut turnstile_exp_state): State<TurnstileExpectedState>) {
+async fn flow_coin(
+    client: Client,
+    State(mut turnstile_exp_state): State<TurnstileExpectedState>,
+) -> Result<(), ClientError> {
     turnstile_instruction::coin(
         &client,
         instruction::Coin {
@@ -66,10 +73,10 @@ async fn flow_coin(client: Client, State(mut turnstile_exp_state): State<Turnsti
         },
         None,
     )
-    .await
-    .expect("coin failed");
+    .await?;
     // Synchronize local state
     turnstile_exp_state.locked = false;
+    Ok(())
 }
 
 async fn init_handler(client: Client, State(turnstile_exp_state): State<TurnstileExpectedState>) {
@@ -88,7 +95,10 @@ async fn init_handler(client: Client, State(turnstile_exp_state): State<Turnstil
     .expect("init failed");
 }
 
-async fn invariant(client: Client, State(turnstile_exp_state): State<TurnstileExpectedState>) {
+async fn invariant(
+    client: Client,
+    StateRef(turnstile_exp_state): StateRef<TurnstileExpectedState>,
+) {
     let state: AccountState = client
         .account_data(turnstile_exp_state.account_state.pubkey())
         .await
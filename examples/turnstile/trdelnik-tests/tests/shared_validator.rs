@@ -0,0 +1,51 @@
+use std::sync::Mutex;
+
+use fehler::throws;
+use trdelnik_client::{solana_sdk::native_token::sol_to_lamports, *};
+
+/// The payers and the namespaces of the tests which have already run.
+static CONTEXTS: Mutex<Vec<(Pubkey, Pubkey)>> = Mutex::new(Vec::new());
+
+fn initialize_validator() -> Validator {
+    let mut validator = Validator::default();
+    validator.add_program("turnstile", turnstile::id());
+    validator
+}
+
+#[fixture]
+async fn init_fixture() -> SharedValidatorContext {
+    shared_validator_with(initialize_validator).await
+}
+
+/// Checks the context of the test against the contexts of the tests which have already run
+/// and leaves a funded account at its namespace for the next tests.
+#[throws]
+async fn check_shared_context(context: SharedValidatorContext) {
+    let SharedValidatorContext { client, namespace } = context;
+    let payer = client.payer().pubkey();
+    assert_eq!(client.get_balance(payer).await?, sol_to_lamports(100.));
+
+    let previous_contexts = CONTEXTS.lock().unwrap().clone();
+    for (previous_payer, previous_namespace) in previous_contexts {
+        assert_ne!(previous_payer, payer);
+        assert_ne!(previous_namespace, namespace);
+        // The account created by the previous test exists, so the validator is the same
+        assert_eq!(
+            client.get_balance(previous_namespace).await?,
+            sol_to_lamports(1.)
+        );
+    }
+
+    client.airdrop(namespace, sol_to_lamports(1.)).await?;
+    CONTEXTS.lock().unwrap().push((payer, namespace));
+}
+
+#[trdelnik_test]
+async fn test_shared_validator_first(#[future] init_fixture: SharedValidatorContext) {
+    check_shared_context(init_fixture.await).await?;
+}
+
+#[trdelnik_test]
+async fn test_shared_validator_second(#[future] init_fixture: SharedValidatorContext) {
+    check_shared_context(init_fixture.await).await?;
+}
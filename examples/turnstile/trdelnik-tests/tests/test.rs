@@ -91,6 +91,43 @@ async fn test_unhappy_path(#[future] init_fixture: Result<Fixture>) {
     assert_eq!(state.res, false);
 }
 
+#[trdelnik_test]
+async fn test_snapshot_restore(#[future] init_fixture: Result<Fixture>) {
+    let fixture = init_fixture.await?;
+    let snapshot = fixture.client.snapshot().await;
+    let captured_state = fixture.get_state().await?;
+
+    // unlock the turnstile and create a new account
+    turnstile_instruction::coin(
+        &fixture.client,
+        instruction::Coin {
+            dummy_arg: "dummy_string".to_owned(),
+        },
+        accounts::UpdateState {
+            state: fixture.state.pubkey(),
+        },
+        None,
+    )
+    .await?;
+    let created = keypair(43);
+    fixture
+        .client
+        .airdrop(created.pubkey(), 1_000_000_000)
+        .await?;
+    assert_eq!(fixture.get_state().await?.locked, false);
+
+    fixture.client.restore(&snapshot).await;
+
+    let state = fixture.get_state().await?;
+    assert_eq!(state.locked, captured_state.locked);
+    assert_eq!(state.res, captured_state.res);
+    assert!(fixture
+        .client
+        .get_account(created.pubkey())
+        .await?
+        .is_none());
+}
+
 struct Fixture {
     client: Client,
     state: Keypair,